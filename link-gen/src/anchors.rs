@@ -0,0 +1,395 @@
+use crate::document::{BlockKind, Document, HeaderLinksSetting, Heading};
+use crate::error::Error;
+use crate::heading::{
+    attribute_id, parse_atx_heading, parse_setext_heading, pin_attribute_id, HeadingLine,
+    IAL_LINE_REGEX,
+};
+use crate::inline::heading_plain_text;
+use crate::link::LinkTemplate;
+use crate::slug::{encode_fragment, slugger_by_name, AnchorIds, KramdownSlugger, Slugger};
+use std::fmt;
+
+/// Settings for one run of `process_markdown_headings`.
+pub struct HeadingOptions {
+    /// Id scheme for files that don't pick one in their front matter.
+    pub slugger: Box<dyn Slugger>,
+    /// Write each generated id onto its heading as an explicit `{#id}`, so
+    /// the anchor no longer depends on the renderer's auto-id rules.
+    pub pin_ids: bool,
+    /// Transliterate headings to ASCII before slugging ("Café" -> "cafe"),
+    /// like kramdown's `transliterated_header_ids`.
+    pub transliterate: bool,
+    /// What the inserted link looks like and where it goes.
+    pub link: LinkTemplate,
+    /// Only headings from `min_level` to `max_level` get a link, unless a
+    /// post lists its own levels in the front matter.
+    pub min_level: usize,
+    pub max_level: usize,
+}
+
+impl Default for HeadingOptions {
+    fn default() -> Self {
+        HeadingOptions {
+            slugger: Box::new(KramdownSlugger),
+            pin_ids: false,
+            transliterate: false,
+            link: LinkTemplate::default(),
+            min_level: 1,
+            max_level: 6,
+        }
+    }
+}
+
+/// The result of `process_markdown_headings`.
+pub struct ProcessedDocument {
+    pub content: String,
+    pub summary: HeadingSummary,
+}
+
+/// What happened to the headings of a document.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HeadingSummary {
+    /// Headings that had no link yet.
+    pub added: usize,
+    /// Headings whose link (or pinned id) changed.
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Filtered-out headings whose old link was taken off.
+    pub removed: usize,
+}
+
+impl HeadingSummary {
+    pub fn changed(&self) -> bool {
+        self.added + self.updated + self.removed > 0
+    }
+}
+
+impl std::ops::AddAssign for HeadingSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.added += other.added;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+        self.removed += other.removed;
+    }
+}
+
+impl fmt::Display for HeadingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} unchanged",
+            self.added, self.updated, self.unchanged
+        )?;
+        if self.removed > 0 {
+            write!(f, ", {} removed", self.removed)?;
+        }
+        Ok(())
+    }
+}
+
+/// One transformation of a document. Commands plug their pass into the
+/// shared file handling: input discovery, destinations, `--check` diffs,
+/// summaries and watch mode.
+pub trait Pass: Sync {
+    fn apply(&self, document: &Document) -> Result<ProcessedDocument, Error>;
+}
+
+/// A read-only validation of a document, run over files like a [`Pass`].
+/// Problems are returned as errors with positions; an `Err` means the
+/// document could not be checked at all.
+pub trait Check: Sync {
+    fn check(&self, document: &Document) -> Result<Vec<Error>, Error>;
+}
+
+// Adds or updates the header links.
+impl Pass for HeadingOptions {
+    fn apply(&self, document: &Document) -> Result<ProcessedDocument, Error> {
+        process_markdown_headings(document, self)
+    }
+}
+
+/// Adds or updates the header links of a document. Posts are rendered by
+/// kramdown on GitHub Pages; a post can pick another scheme with a
+/// `slugger:` front matter key.
+pub fn process_markdown_headings(
+    document: &Document,
+    options: &HeadingOptions,
+) -> Result<ProcessedDocument, Error> {
+    let lines = &document.lines;
+    let mut output: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    let mut summary = HeadingSummary::default();
+    for scanned in scan_headings(document, options)? {
+        let ScannedHeading {
+            line: heading,
+            heading: Heading { anchor, .. },
+            explicit_id,
+            attributes: _,
+            linked,
+            had_link,
+        } = scanned;
+
+        if !linked {
+            // Headings that are filtered out still take up their id, but
+            // lose any link an earlier run gave them.
+            if had_link {
+                let attributes = heading
+                    .attributes
+                    .map(|attributes| format!(" {}", attributes))
+                    .unwrap_or_default();
+                output[heading.line] = format!(
+                    "{}{}{}{}",
+                    heading.prefix, heading.text, heading.closing, attributes
+                );
+                summary.removed += 1;
+            }
+            continue;
+        }
+
+        let attributes = match heading.attributes {
+            Some(attributes) if explicit_id || !options.pin_ids => {
+                format!(" {}", attributes)
+            }
+            Some(attributes) => format!(" {}", pin_attribute_id(attributes, &anchor)),
+            None if options.pin_ids && !explicit_id => format!(" {{#{}}}", anchor),
+            None => String::new(),
+        };
+
+        // Construct the new line with the header link added
+        output[heading.line] = format!(
+            "{}{}{}{}",
+            heading.prefix,
+            options.link.render(heading.text, &encode_fragment(&anchor)),
+            heading.closing,
+            attributes,
+        );
+        if output[heading.line] == lines[heading.line] {
+            summary.unchanged += 1;
+        } else if had_link {
+            summary.updated += 1;
+        } else {
+            summary.added += 1;
+        }
+    }
+
+    Ok(ProcessedDocument {
+        content: document.reassemble(&output),
+        summary,
+    })
+}
+
+// A heading found by `scan_headings`, with what is needed to rewrite it.
+pub(crate) struct ScannedHeading<'a> {
+    pub(crate) line: HeadingLine<'a>,
+    pub(crate) heading: Heading,
+    // The heading's attribute list, trailing or on the next line.
+    pub(crate) attributes: Option<&'a str>,
+    // Whether the anchor comes from a `{#id}` rather than the slugger.
+    pub(crate) explicit_id: bool,
+    // Whether the heading's level gets a link.
+    pub(crate) linked: bool,
+    // Whether an earlier run already gave it one.
+    pub(crate) had_link: bool,
+}
+
+// Finds the headings of a document and hands out their anchors, in order,
+// as they are once every heading that gets a link has one.
+pub(crate) fn scan_headings<'a>(
+    document: &Document<'a>,
+    options: &HeadingOptions,
+) -> Result<Vec<ScannedHeading<'a>>, Error> {
+    scan(document, options, false)
+}
+
+// Like `scan_headings`, but with the anchors the document renders to as it
+// is: only headings that already have a link get its text in their id.
+pub(crate) fn scan_rendered_headings<'a>(
+    document: &Document<'a>,
+    options: &HeadingOptions,
+) -> Result<Vec<ScannedHeading<'a>>, Error> {
+    scan(document, options, true)
+}
+
+fn scan<'a>(
+    document: &Document<'a>,
+    options: &HeadingOptions,
+    as_rendered: bool,
+) -> Result<Vec<ScannedHeading<'a>>, Error> {
+    // Settings that cannot be read leave the document alone.
+    let front_matter = document.front_matter()?;
+    let Document { lines, kinds, .. } = document;
+    // An unknown name is one of the document's warnings.
+    let file_slugger = front_matter.slugger.as_deref().and_then(slugger_by_name);
+    let linked_levels = match &front_matter.header_links {
+        Some(HeaderLinksSetting::Enabled(false)) => Vec::new(),
+        Some(HeaderLinksSetting::Levels { levels }) => levels.clone(),
+        Some(HeaderLinksSetting::Enabled(true)) | None => {
+            (options.min_level..=options.max_level).collect()
+        }
+    };
+    let mut anchor_ids = AnchorIds::new(
+        file_slugger.as_deref().unwrap_or(&*options.slugger),
+        options.transliterate,
+        &options.link,
+    );
+    let link_regex = options.link.link_regex()?;
+
+    let mut headings = Vec::new();
+    for index in 0..lines.len() {
+        // Only genuine headings count; `#` lines inside code fences, HTML
+        // blocks or the front matter are left alone.
+        let heading = match kinds[index] {
+            BlockKind::Heading => parse_atx_heading(lines[index], index, &link_regex),
+            BlockKind::SetextUnderline => parse_setext_heading(lines, kinds, index, &link_regex),
+            _ => None,
+        };
+        let Some(heading) = heading else {
+            continue;
+        };
+        let linked = linked_levels.contains(&heading.level);
+        let had_link = link_regex.is_match(lines[heading.line]);
+
+        // kramdown also accepts the attribute list on the line after the
+        // heading.
+        let next_line_attributes = lines
+            .get(index + 1)
+            .filter(|_| kinds[index + 1] == BlockKind::Paragraph)
+            .and_then(|next| IAL_LINE_REGEX.find(next.trim()));
+        let attributes = heading
+            .attributes
+            .or(next_line_attributes.map(|m| m.as_str()));
+        let explicit_id = attributes.and_then(attribute_id);
+
+        let text = heading_plain_text(&heading.full_text());
+        let position = (
+            heading.line + 1,
+            heading.prefix.chars().count() + 1,
+            heading.text.chars().count(),
+        );
+        let anchor = match explicit_id {
+            Some(id) => {
+                anchor_ids.claim(id, position)?;
+                id.to_string()
+            }
+            None => {
+                let with_link = if as_rendered { had_link } else { linked };
+                anchor_ids.next(&text, heading.level, position, with_link)?
+            }
+        };
+
+        let first_line = heading.line - heading.leading_lines.len();
+        headings.push(ScannedHeading {
+            heading: Heading {
+                level: heading.level,
+                text,
+                anchor,
+                lines: first_line..index + 1,
+            },
+            line: heading,
+            attributes,
+            explicit_id: explicit_id.is_some(),
+            linked,
+            had_link,
+        });
+    }
+    Ok(headings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POST: &str = "---\r\n\
+                        title: A post\r\n\
+                        ---\r\n\
+                        # Title\r\n\
+                        \r\n\
+                        ## Closing ##\r\n\
+                        \r\n\
+                        Setext *heading*\r\n\
+                        ---\r\n\
+                        \r\n\
+                        ## Example\r\n\
+                        \r\n\
+                        ## Example {: .x}\r\n\
+                        \r\n\
+                        ### Explicit {#mine}\r\n\
+                        \r\n\
+                        ```md\r\n\
+                        ## In code\r\n\
+                        ```";
+
+    fn process(content: &str, options: &HeadingOptions) -> ProcessedDocument {
+        process_markdown_headings(&Document::parse(content), options).unwrap()
+    }
+
+    #[test]
+    fn header_links() {
+        let processed = process(POST, &HeadingOptions::default());
+        let link = |id: &str| format!(r##"<a href="#{}" class="header-link">🔗</a>"##, id);
+        let lines: Vec<&str> = processed.content.split("\r\n").collect();
+        assert_eq!(lines[3], format!("# Title {}", link("title-")));
+        assert_eq!(lines[5], format!("## Closing {} ##", link("closing-")));
+        assert_eq!(
+            lines[7],
+            format!("Setext *heading* {}", link("setext-heading-"))
+        );
+        assert_eq!(lines[10], format!("## Example {}", link("example-")));
+        assert_eq!(
+            lines[12],
+            format!("## Example {} {{: .x}}", link("example--1"))
+        );
+        assert_eq!(
+            lines[14],
+            format!("### Explicit {} {{#mine}}", link("mine"))
+        );
+        assert_eq!(lines[17], "## In code");
+        assert_eq!(lines[18], "```");
+        assert_eq!(processed.summary.added, 6);
+    }
+
+    #[test]
+    fn a_second_run_changes_nothing() {
+        let pinned = HeadingOptions {
+            pin_ids: true,
+            ..HeadingOptions::default()
+        };
+        let levels = HeadingOptions {
+            min_level: 2,
+            max_level: 2,
+            ..HeadingOptions::default()
+        };
+        for options in [HeadingOptions::default(), pinned, levels] {
+            let first = process(POST, &options);
+            assert!(first.summary.changed());
+            let second = process(&first.content, &options);
+            assert_eq!(second.content, first.content);
+            assert!(!second.summary.changed());
+            assert_eq!(second.summary.unchanged, first.summary.added);
+        }
+    }
+
+    #[test]
+    fn filtered_out_headings_lose_their_link() {
+        let first = process(POST, &HeadingOptions::default());
+        let levels = HeadingOptions {
+            min_level: 2,
+            max_level: 2,
+            ..HeadingOptions::default()
+        };
+        let second = process(&first.content, &levels);
+        assert_eq!(second.summary.removed, 2);
+        let lines: Vec<&str> = second.content.split("\r\n").collect();
+        assert_eq!(lines[3], "# Title");
+        assert_eq!(lines[14], "### Explicit {#mine}");
+    }
+
+    #[test]
+    fn malformed_settings_leave_the_document_alone() {
+        let document = Document::parse("---\nheader_links: no\n---\n## A\n");
+        let result = process_markdown_headings(&document, &HeadingOptions::default());
+        assert!(matches!(
+            result,
+            Err(Error::MalformedFrontMatter { line: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,371 @@
+use crate::anchors::HeadingSummary;
+use crate::error::{line_and_column, split_yaml_error, Error};
+use crate::link::LinkPosition;
+use crate::number::NumberStyle;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Project settings from `linkgen.toml`, or from the `linkgen:` section of
+/// Jekyll's `_config.yml`. Every key is optional and command-line flags take
+/// precedence; unknown keys are an error so typos don't go unnoticed.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The directory the settings were found in; `include` and `exclude`
+    /// are relative to it.
+    #[serde(skip)]
+    pub root: PathBuf,
+    /// The file the settings were read from, if any.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    pub slugger: Option<String>,
+    pub pin_ids: Option<bool>,
+    pub transliterate: Option<bool>,
+    pub theme: Option<String>,
+    pub link: LinkConfig,
+    #[serde(deserialize_with = "heading_level")]
+    pub min_level: Option<u8>,
+    #[serde(deserialize_with = "heading_level")]
+    pub max_level: Option<u8>,
+    /// What `--all` and `watch` process when given no paths.
+    pub include: Vec<String>,
+    /// Glob patterns of files to leave alone when expanding directories
+    /// and globs; files named on the command line are always processed.
+    pub(crate) exclude: Vec<String>,
+    #[serde(skip)]
+    exclude_patterns: Vec<glob::Pattern>,
+    pub check: CheckConfig,
+    pub toc: TocConfig,
+    pub number: NumberConfig,
+}
+
+/// Overrides for the theme's header link, as in the `--link-*` flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkConfig {
+    pub element: Option<String>,
+    pub symbol: Option<String>,
+    pub class: Option<String>,
+    pub position: Option<LinkPosition>,
+    pub aria_label: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Which pending changes make `--check` fail.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckConfig {
+    pub fail_on: Vec<ChangeKind>,
+}
+
+/// Settings for the `toc` command, as in its flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TocConfig {
+    #[serde(deserialize_with = "heading_level")]
+    pub min_depth: Option<u8>,
+    #[serde(deserialize_with = "heading_level")]
+    pub max_depth: Option<u8>,
+    /// Headings to leave out, by text or anchor.
+    pub exclude: Vec<String>,
+}
+
+/// Settings for the `number` command, as in its flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NumberConfig {
+    #[serde(deserialize_with = "heading_level")]
+    pub min_depth: Option<u8>,
+    #[serde(deserialize_with = "heading_level")]
+    pub max_depth: Option<u8>,
+    pub style: Option<NumberStyle>,
+}
+
+// A heading level, checked while parsing so the error points at the value.
+fn heading_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    let level = u8::deserialize(deserializer)?;
+    if (1..=6).contains(&level) {
+        Ok(Some(level))
+    } else {
+        Err(de::Error::custom("heading levels go from 1 to 6"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Updated,
+    Removed,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            root: PathBuf::new(),
+            path: None,
+            slugger: None,
+            pin_ids: None,
+            transliterate: None,
+            theme: None,
+            link: LinkConfig::default(),
+            min_level: None,
+            max_level: None,
+            include: vec!["_posts".to_string()],
+            exclude: Vec::new(),
+            exclude_patterns: Vec::new(),
+            check: CheckConfig::default(),
+            toc: TocConfig::default(),
+            number: NumberConfig::default(),
+        }
+    }
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        CheckConfig {
+            fail_on: vec![ChangeKind::Added, ChangeKind::Updated, ChangeKind::Removed],
+        }
+    }
+}
+
+impl CheckConfig {
+    pub fn fails(&self, summary: &HeadingSummary) -> bool {
+        self.fail_on.iter().any(|kind| match kind {
+            ChangeKind::Added => summary.added > 0,
+            ChangeKind::Updated => summary.updated > 0,
+            ChangeKind::Removed => summary.removed > 0,
+        })
+    }
+}
+
+// Only read for its `linkgen:` section; the rest belongs to Jekyll.
+#[derive(Deserialize)]
+struct JekyllConfig {
+    linkgen: Option<Config>,
+}
+
+impl Config {
+    /// Loads `path`, or else the nearest `linkgen.toml` or `_config.yml`
+    /// with a `linkgen:` section in the working directory or above it.
+    /// Without either, everything is left to the command line.
+    pub fn load(path: Option<&Path>) -> Result<Config, Error> {
+        if let Some(path) = path {
+            return Config::read(path)?.ok_or_else(|| Error::Config {
+                path: path.to_path_buf(),
+                position: None,
+                message: "no `linkgen:` section".to_string(),
+            });
+        }
+        Config::discover(Path::new(""))
+    }
+
+    // Looks for the settings in `start` and the directories above it.
+    fn discover(start: &Path) -> Result<Config, Error> {
+        // Walk up with relative paths so reported file names stay short.
+        let absolute = if start.as_os_str().is_empty() {
+            std::env::current_dir()?
+        } else {
+            fs::canonicalize(start)?
+        };
+        for depth in 0..absolute.ancestors().count() {
+            let dir = start.join(std::iter::repeat_n("..", depth).collect::<PathBuf>());
+            for name in ["linkgen.toml", "_config.yml"] {
+                let candidate = dir.join(name);
+                if candidate.is_file() {
+                    if let Some(config) = Config::read(&candidate)? {
+                        return Ok(config);
+                    }
+                }
+            }
+        }
+        Ok(Config::default())
+    }
+
+    // `Ok(None)` for a Jekyll config without a `linkgen:` section.
+    fn read(path: &Path) -> Result<Option<Config>, Error> {
+        let fail = |position, message| Error::Config {
+            path: path.to_path_buf(),
+            position,
+            message,
+        };
+        let text = fs::read_to_string(path).map_err(|err| fail(None, err.to_string()))?;
+        let config = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str::<Config>(&text).map_err(|err| {
+                let position = err.span().map(|span| line_and_column(&text, span.start));
+                fail(position, err.message().to_string())
+            })?
+        } else {
+            let parsed = serde_yaml::from_str::<JekyllConfig>(&text).map_err(|err| {
+                let (position, message) = split_yaml_error(&err);
+                fail(position, message)
+            })?;
+            match parsed.linkgen {
+                Some(config) => config,
+                None => return Ok(None),
+            }
+        };
+        let exclude_patterns = config
+            .exclude
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern)
+                    .map_err(|err| fail(None, format!("exclude pattern `{}`: {}", pattern, err)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Config {
+            root: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            path: Some(path.to_path_buf()),
+            exclude_patterns,
+            ..config
+        }))
+    }
+
+    /// The `include` entries, as paths from the working directory.
+    pub fn include_paths(&self) -> Vec<PathBuf> {
+        self.include
+            .iter()
+            .map(|entry| self.root.join(entry))
+            .collect()
+    }
+
+    pub fn excludes(&self, path: &Path) -> bool {
+        if self.exclude_patterns.is_empty() {
+            return false;
+        }
+        let root = if self.root.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.root
+        };
+        let (Ok(path), Ok(root)) = (fs::canonicalize(path), fs::canonicalize(root)) else {
+            return false;
+        };
+        let relative = path.strip_prefix(&root).unwrap_or(&path);
+        self.exclude_patterns
+            .iter()
+            .any(|pattern| pattern.matches_path(relative))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::tests::Scratch;
+
+    fn config_error(result: Result<Config, Error>) -> (Option<(usize, usize)>, String) {
+        match result {
+            Err(Error::Config {
+                position, message, ..
+            }) => (position, message),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn walks_up_to_the_nearest_config() {
+        let scratch = Scratch::new("walk");
+        scratch.write("linkgen.toml", "slugger = \"github\"\n");
+        scratch.write("site/_posts/post.md", "# Post\n");
+        let config = Config::discover(&scratch.0.join("site/_posts")).unwrap();
+        assert_eq!(config.slugger.as_deref(), Some("github"));
+        assert_eq!(
+            fs::canonicalize(&config.root).unwrap(),
+            fs::canonicalize(&scratch.0).unwrap()
+        );
+        assert_eq!(
+            config.include_paths(),
+            vec![config.root.join("_posts")],
+            "include is relative to the config"
+        );
+    }
+
+    #[test]
+    fn jekyll_config_section() {
+        let scratch = Scratch::new("jekyll");
+        scratch.write("linkgen.toml", "slugger = \"github\"\n");
+        scratch.write(
+            "site/_config.yml",
+            "title: Blog\nlinkgen:\n  slugger: kramdown\n",
+        );
+        let config = Config::discover(&scratch.0.join("site")).unwrap();
+        assert_eq!(config.slugger.as_deref(), Some("kramdown"));
+        assert_eq!(config.path, Some(scratch.0.join("site/_config.yml")));
+    }
+
+    #[test]
+    fn jekyll_config_without_a_section_is_skipped() {
+        let scratch = Scratch::new("no-section");
+        scratch.write("linkgen.toml", "slugger = \"github\"\n");
+        let jekyll = scratch.write("site/_config.yml", "title: Blog\n");
+        let config = Config::discover(&scratch.0.join("site")).unwrap();
+        assert_eq!(config.slugger.as_deref(), Some("github"));
+
+        // Named explicitly, the missing section is an error.
+        let (position, message) = config_error(Config::load(Some(&jekyll)));
+        assert_eq!(position, None);
+        assert_eq!(message, "no `linkgen:` section");
+    }
+
+    #[test]
+    fn unknown_keys_are_errors_with_positions() {
+        let scratch = Scratch::new("unknown");
+        let toml = scratch.write("linkgen.toml", "slugger = \"github\"\npin_id = true\n");
+        let (position, message) = config_error(Config::load(Some(&toml)));
+        assert_eq!(position, Some((2, 1)));
+        assert!(message.contains("unknown field `pin_id`"), "{}", message);
+
+        let yaml = scratch.write("_config.yml", "linkgen:\n  toc:\n    max_dept: 3\n");
+        let (position, message) = config_error(Config::load(Some(&yaml)));
+        assert_eq!(position.map(|(line, _)| line), Some(3));
+        assert!(message.contains("unknown field `max_dept`"), "{}", message);
+    }
+
+    #[test]
+    fn heading_levels_are_checked() {
+        let scratch = Scratch::new("levels");
+        let toml = scratch.write("linkgen.toml", "[toc]\nmin_depth = 2\nmax_depth = 9\n");
+        let (position, message) = config_error(Config::load(Some(&toml)));
+        assert_eq!(position, Some((3, 13)));
+        assert_eq!(message, "heading levels go from 1 to 6");
+
+        let toml = scratch.write("linkgen.toml", "min_level = 2\nmax_level = 4\n");
+        let config = Config::load(Some(&toml)).unwrap();
+        assert_eq!((config.min_level, config.max_level), (Some(2), Some(4)));
+    }
+
+    #[test]
+    fn excludes() {
+        let scratch = Scratch::new("exclude");
+        let toml = scratch.write("linkgen.toml", "exclude = [\"_posts/drafts/*\"]\n");
+        let draft = scratch.write("_posts/drafts/idea.md", "# Idea\n");
+        let post = scratch.write("_posts/post.md", "# Post\n");
+        let config = Config::load(Some(&toml)).unwrap();
+        assert!(config.excludes(&draft));
+        assert!(!config.excludes(&post));
+        assert!(!Config::default().excludes(&draft));
+
+        let toml = scratch.write("linkgen.toml", "exclude = [\"[\"]\n");
+        let (_, message) = config_error(Config::load(Some(&toml)));
+        assert!(message.starts_with("exclude pattern `[`"), "{}", message);
+    }
+
+    #[test]
+    fn fail_on() {
+        let summary = HeadingSummary {
+            removed: 1,
+            ..HeadingSummary::default()
+        };
+        assert!(CheckConfig::default().fails(&summary));
+        let check = CheckConfig {
+            fail_on: vec![ChangeKind::Added, ChangeKind::Updated],
+        };
+        assert!(!check.fails(&summary));
+        assert!(check.fails(&HeadingSummary {
+            updated: 2,
+            ..HeadingSummary::default()
+        }));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::error::{Error, Severity};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+/// An [`Error`] with everything needed to show it: the file, the position,
+/// the offending source line and a hint. Displays like a compiler message
+/// and serializes to JSON for editors.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The column just past the offending text.
+    pub end_column: Option<usize>,
+    /// The source line the problem is on.
+    pub snippet: Option<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    /// `source` is the text of `file`, used for the snippet.
+    pub fn new(error: &Error, file: Option<&Path>, source: Option<&str>) -> Self {
+        let position = error.position();
+        let snippet = position.and_then(|(line, _)| {
+            let source = source?;
+            let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+            let text = source.lines().nth(line.checked_sub(1)?)?;
+            Some(text.trim_end().to_string())
+        });
+        Diagnostic {
+            severity: error.severity(),
+            code: error.code(),
+            message: error.to_string(),
+            file: file.or(error.path()).map(|path| path.display().to_string()),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            end_column: position.map(|(_, column)| column + error.width()),
+            snippet,
+            help: error.help(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}[{}]: {}", severity, self.code, self.message)?;
+
+        let location = match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => format!("{}:{}:{}", file, line, column),
+            (Some(file), _, _) => file.clone(),
+            (None, Some(line), Some(column)) => format!("<stdin>:{}:{}", line, column),
+            (None, _, _) => String::new(),
+        };
+        let gutter = " ".repeat(self.line.map_or(0, |line| line.to_string().len()));
+        if !location.is_empty() {
+            write!(f, "\n{} --> {}", gutter, location)?;
+        }
+        if let (Some(snippet), Some(line), Some(column)) = (&self.snippet, self.line, self.column) {
+            // Underline the offending text, up to the end of the line,
+            // keeping tabs so the carets line up under it.
+            let before: String = snippet
+                .chars()
+                .take(column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let rest = snippet
+                .chars()
+                .count()
+                .saturating_sub(column.saturating_sub(1));
+            let width = self
+                .end_column
+                .map_or(1, |end| end.saturating_sub(column))
+                .min(rest)
+                .max(1);
+            write!(f, "\n{} |", gutter)?;
+            write!(f, "\n{} | {}", line, snippet)?;
+            write!(f, "\n{} | {}{}", gutter, before, "^".repeat(width))?;
+        }
+        if let Some(help) = &self.help {
+            write!(f, "\n{} = help: {}", gutter, help)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "# Intro\n\nSee [the end](#ending) and\tmore.\n";
+
+    fn broken_link() -> Error {
+        Error::BrokenLink {
+            line: 3,
+            column: 15,
+            width: 7,
+            target: "#ending".to_string(),
+            suggestion: Some("#end".to_string()),
+        }
+    }
+
+    #[test]
+    fn human_rendering() {
+        let diagnostic = Diagnostic::new(&broken_link(), Some(Path::new("post.md")), Some(SOURCE));
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[broken-link]: link to `#ending` does not match any anchor\n\
+             \x20 --> post.md:3:15\n\
+             \x20 |\n\
+             3 | See [the end](#ending) and\tmore.\n\
+             \x20 |               ^^^^^^^\n\
+             \x20 = help: did you mean `#end`?"
+        );
+    }
+
+    #[test]
+    fn carets_stop_at_the_end_of_the_line() {
+        let error = Error::UnclosedFence {
+            line: 1,
+            column: 3,
+            width: 3,
+            fence: "```".to_string(),
+        };
+        let diagnostic = Diagnostic::new(&error, None, Some("  ``"));
+        assert!(diagnostic.to_string().contains("\n  |   ^^\n"));
+    }
+
+    #[test]
+    fn without_a_position() {
+        let error = Error::Config {
+            path: "linkgen.toml".into(),
+            position: None,
+            message: "no `linkgen:` section".to_string(),
+        };
+        let diagnostic = Diagnostic::new(&error, None, None);
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[config]: no `linkgen:` section\n --> linkgen.toml"
+        );
+    }
+
+    #[test]
+    fn json_rendering() {
+        let diagnostic = Diagnostic::new(&broken_link(), None, Some(SOURCE));
+        assert_eq!(
+            serde_json::to_value(&diagnostic).unwrap(),
+            serde_json::json!({
+                "severity": "error",
+                "code": "broken-link",
+                "message": "link to `#ending` does not match any anchor",
+                "file": null,
+                "line": 3,
+                "column": 15,
+                "end_column": 22,
+                "snippet": "See [the end](#ending) and\tmore.",
+                "help": "did you mean `#end`?",
+            })
+        );
+    }
+}
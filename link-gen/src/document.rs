@@ -0,0 +1,850 @@
+use crate::anchors::{scan_headings, HeadingOptions};
+use crate::error::{split_yaml_error, Error};
+use crate::slug::slugger_by_name;
+use regex::Regex;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// A Markdown document as every command sees it: its lines, the kind of
+/// block each line belongs to, and the settings in its front matter.
+pub struct Document<'a> {
+    pub(crate) lines: Vec<&'a str>,
+    // The terminator of each line, `\n`, `\r\n` or nothing on a last line
+    // without one, so untouched lines are written back byte for byte.
+    pub(crate) endings: Vec<&'a str>,
+    // A UTF-8 byte order mark the file starts with.
+    pub(crate) bom: &'a str,
+    pub(crate) kinds: Vec<BlockKind>,
+    front_matter: Result<FrontMatter, FrontMatterProblem>,
+    pub(crate) warnings: Vec<Error>,
+}
+
+/// A run of lines forming one block. Line numbers are 0-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub kind: BlockKind,
+    pub lines: Range<usize>,
+}
+
+/// A heading and the anchor it ends up with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: usize,
+    /// The text a reader sees, without inline Markdown or header links.
+    pub text: String,
+    /// The explicit `{#id}` if there is one, otherwise the generated id.
+    pub anchor: String,
+    /// The lines of the heading, including a setext underline; 0-based.
+    pub lines: Range<usize>,
+}
+
+impl<'a> Document<'a> {
+    /// Parsing never fails; problems that don't stop the document from
+    /// being processed end up in `warnings`.
+    pub fn parse(content: &'a str) -> Self {
+        let (bom, content) = match content.strip_prefix('\u{feff}') {
+            Some(rest) => (&content[..content.len() - rest.len()], rest),
+            None => ("", content),
+        };
+        let (lines, endings): (Vec<&str>, Vec<&str>) =
+            content.split_inclusive('\n').map(split_ending).unzip();
+        let (kinds, unclosed_fence) = classify_lines(&lines);
+        let mut warnings = Vec::new();
+        let front_matter = parse_front_matter(&lines, &kinds);
+        if let Some(name) = front_matter
+            .as_ref()
+            .ok()
+            .and_then(|fm| fm.slugger.as_ref())
+        {
+            if slugger_by_name(name).is_none() {
+                let (line, column, width) = front_matter_key_position(&lines, &kinds, "slugger");
+                warnings.push(Error::UnknownSlugger {
+                    line,
+                    column,
+                    width,
+                    name: name.clone(),
+                });
+            }
+        }
+        if let Some(index) = unclosed_fence {
+            let (indent, rest) =
+                lines[index].split_at(lines[index].len() - lines[index].trim_start().len());
+            let marker = rest.chars().next().unwrap_or('`');
+            let fence: String = rest.chars().take_while(|&c| c == marker).collect();
+            warnings.push(Error::UnclosedFence {
+                line: index + 1,
+                column: indent.chars().count() + 1,
+                width: fence.chars().count(),
+                fence,
+            });
+        }
+        Document {
+            lines,
+            endings,
+            bom,
+            kinds,
+            front_matter,
+            warnings,
+        }
+    }
+
+    pub fn lines(&self) -> &[&'a str] {
+        &self.lines
+    }
+
+    /// Puts the document back together from replacement lines, one per
+    /// line of the document, with the original line endings and BOM.
+    pub(crate) fn reassemble(&self, lines: &[String]) -> String {
+        let mut content = String::from(self.bom);
+        for (line, ending) in lines.iter().zip(&self.endings) {
+            content.push_str(line);
+            content.push_str(ending);
+        }
+        content
+    }
+
+    // Replaces a range of lines, which may change how many there are. New
+    // lines get the file's usual line ending.
+    pub(crate) fn splice(&self, range: Range<usize>, replacement: &[String]) -> String {
+        let newline = self
+            .endings
+            .iter()
+            .find(|ending| !ending.is_empty())
+            .copied()
+            .unwrap_or("\n");
+        let original = self.lines.iter().copied().zip(self.endings.iter().copied());
+        let lines: Vec<(&str, &str)> = original
+            .clone()
+            .take(range.start)
+            .chain(replacement.iter().map(|line| (line.as_str(), newline)))
+            .chain(original.skip(range.end))
+            .collect();
+        let mut content = String::from(self.bom);
+        for (index, (line, ending)) in lines.iter().enumerate() {
+            content.push_str(line);
+            // The old last line may not be the last one any more.
+            if ending.is_empty() && index + 1 < lines.len() {
+                content.push_str(newline);
+            } else {
+                content.push_str(ending);
+            }
+        }
+        content
+    }
+
+    /// The link-gen settings in the front matter. If they cannot be read,
+    /// nothing should touch the document: a post that opts out of header
+    /// links with a typo must not get them anyway.
+    pub fn front_matter(&self) -> Result<&FrontMatter, Error> {
+        self.front_matter
+            .as_ref()
+            .map_err(|problem| Error::MalformedFrontMatter {
+                line: problem.line,
+                column: problem.column,
+                // serde_yaml only knows where the problem starts.
+                width: 1,
+                message: problem.message.clone(),
+            })
+    }
+
+    // The raw front matter, for the Jekyll keys `FrontMatter` leaves out.
+    pub(crate) fn front_matter_yaml(&self) -> String {
+        front_matter_yaml(&self.lines, &self.kinds)
+    }
+
+    /// Problems found while parsing, such as an unclosed code fence.
+    pub fn warnings(&self) -> &[Error] {
+        &self.warnings
+    }
+
+    /// The blocks of the document in order. A setext heading is one block
+    /// with its underline; consecutive blank lines form one `Blank` block.
+    pub fn blocks(&self) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut fence = None;
+        // Whether the last block ended on the previous line even if the next
+        // line has the same kind, as with back-to-back fenced code blocks.
+        let mut closed = true;
+        for (index, (&line, &kind)) in self.lines.iter().zip(&self.kinds).enumerate() {
+            let block_kind = match kind {
+                BlockKind::SetextUnderline => BlockKind::SetextHeading,
+                kind => kind,
+            };
+            match blocks.last_mut() {
+                Some(block) if !closed && block.kind == block_kind => block.lines.end = index + 1,
+                _ => blocks.push(Block {
+                    kind: block_kind,
+                    lines: index..index + 1,
+                }),
+            }
+            closed = match kind {
+                BlockKind::Heading | BlockKind::SetextUnderline => true,
+                BlockKind::FencedCode => {
+                    fence = match fence {
+                        None => open_fence(split_indent(line).1),
+                        Some(open) if closes_fence(line, open) => None,
+                        open => open,
+                    };
+                    fence.is_none()
+                }
+                _ => false,
+            };
+        }
+        blocks
+    }
+
+    /// The headings of the document with the anchors `options` give them,
+    /// taking the front matter's settings into account.
+    pub fn headings(&self, options: &HeadingOptions) -> Result<Vec<Heading>, Error> {
+        Ok(scan_headings(self, options)?
+            .into_iter()
+            .map(|scanned| scanned.heading)
+            .collect())
+    }
+}
+
+/// The CommonMark block a line belongs to, as far as heading detection cares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKind {
+    FrontMatter,
+    FencedCode,
+    IndentedCode,
+    HtmlBlock,
+    /// An ATX heading.
+    Heading,
+    /// The text lines of a setext heading, and the `===`/`---` line under them.
+    SetextHeading,
+    SetextUnderline,
+    Paragraph,
+    Blank,
+}
+
+// An open ``` or ~~~ fence: the fence character and how many of them opened it.
+#[derive(Debug, Clone, Copy)]
+struct Fence {
+    marker: char,
+    len: usize,
+}
+
+// How an HTML block ends (CommonMark spec, section 4.6).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HtmlEnd {
+    // Types 1-5: the first line containing one of these (case-insensitive).
+    Terminator(&'static [&'static str]),
+    // Types 6 and 7: the next blank line.
+    BlankLine,
+}
+
+// Tag names that start a type 6 HTML block.
+const HTML_BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+static HTML_OPEN_TAG_REGEX: LazyLock<Regex> = static_regex!(
+    r#"^<[A-Za-z][A-Za-z0-9-]*(?:\s+[A-Za-z_:][A-Za-z0-9_.:-]*(?:\s*=\s*(?:[^\s"'=<>`]+|'[^']*'|"[^"]*"))?)*\s*/?>\s*$"#
+);
+static HTML_CLOSE_TAG_REGEX: LazyLock<Regex> = static_regex!(r"^</[A-Za-z][A-Za-z0-9-]*\s*>\s*$");
+
+// Walks the document once and labels every line with the block it belongs to.
+// Also returns the line of a code fence left open at the end.
+pub(crate) fn classify_lines(lines: &[&str]) -> (Vec<BlockKind>, Option<usize>) {
+    let mut kinds = Vec::with_capacity(lines.len());
+    let front_matter_end = find_front_matter_end(lines);
+    let mut fence: Option<Fence> = None;
+    let mut fence_start = 0;
+    let mut html: Option<HtmlEnd> = None;
+    let mut in_paragraph = false;
+    // Where the current paragraph started, and whether an underline below it
+    // would turn it into a setext heading. List items, block quotes and
+    // tables are followed by a thematic break instead.
+    let mut paragraph_start = 0;
+    let mut setext_candidate = false;
+
+    for (index, line) in lines.iter().enumerate() {
+        if front_matter_end.is_some_and(|end| index <= end) {
+            kinds.push(BlockKind::FrontMatter);
+            continue;
+        }
+
+        if let Some(open) = fence {
+            if closes_fence(line, open) {
+                fence = None;
+            }
+            kinds.push(BlockKind::FencedCode);
+            continue;
+        }
+
+        if let Some(end) = html {
+            if end == HtmlEnd::BlankLine && line.trim().is_empty() {
+                html = None;
+            } else {
+                if html_block_ends(line, end) {
+                    html = None;
+                }
+                kinds.push(BlockKind::HtmlBlock);
+                continue;
+            }
+        }
+
+        let (indent, rest) = split_indent(line);
+        if in_paragraph && setext_candidate && indent < 4 && is_setext_underline(rest) {
+            kinds[paragraph_start..index].fill(BlockKind::SetextHeading);
+            kinds.push(BlockKind::SetextUnderline);
+            in_paragraph = false;
+            continue;
+        }
+
+        let kind = if rest.trim().is_empty() {
+            BlockKind::Blank
+        } else if indent >= 4 {
+            // Indented code cannot interrupt a paragraph; there it is a
+            // lazy continuation line instead.
+            if in_paragraph {
+                BlockKind::Paragraph
+            } else {
+                BlockKind::IndentedCode
+            }
+        } else if let Some(open) = open_fence(rest) {
+            fence = Some(open);
+            fence_start = index;
+            BlockKind::FencedCode
+        } else if let Some(end) = open_html_block(rest, in_paragraph) {
+            if end == HtmlEnd::BlankLine || !html_block_ends(rest, end) {
+                html = Some(end);
+            }
+            BlockKind::HtmlBlock
+        } else if is_atx_heading(rest) {
+            BlockKind::Heading
+        } else {
+            BlockKind::Paragraph
+        };
+
+        if kind == BlockKind::Paragraph {
+            if starts_container(rest) {
+                paragraph_start = index;
+                setext_candidate = false;
+            } else if !in_paragraph {
+                paragraph_start = index;
+                setext_candidate = true;
+            }
+        }
+        in_paragraph = kind == BlockKind::Paragraph;
+        kinds.push(kind);
+    }
+
+    (kinds, fence.map(|_| fence_start))
+}
+
+// Jekyll front matter: a `---` first line, closed by `---` or `...`.
+// Returns the index of the closing line.
+fn find_front_matter_end(lines: &[&str]) -> Option<usize> {
+    if lines.first()?.trim_end() != "---" {
+        return None;
+    }
+    lines
+        .iter()
+        .skip(1)
+        .position(|line| matches!(line.trim_end(), "---" | "..."))
+        .map(|position| position + 1)
+}
+
+/// The front matter keys link-gen reads; everything else is Jekyll's.
+#[derive(Debug, Default, Deserialize)]
+pub struct FrontMatter {
+    /// Overrides the run's slugger for this post.
+    pub slugger: Option<String>,
+    pub header_links: Option<HeaderLinksSetting>,
+}
+
+/// `header_links: false` opts a post out entirely;
+/// `header_links: {levels: [2, 3, 4]}` picks the levels that get a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderLinksSetting {
+    Enabled(bool),
+    Levels { levels: Vec<usize> },
+}
+
+// serde's own message for an untagged enum doesn't say what is expected.
+impl<'de> Deserialize<'de> for HeaderLinksSetting {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Levels {
+            levels: Vec<usize>,
+        }
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Setting {
+            Enabled(bool),
+            Levels(Levels),
+        }
+        match Setting::deserialize(deserializer) {
+            Ok(Setting::Enabled(enabled)) => Ok(HeaderLinksSetting::Enabled(enabled)),
+            Ok(Setting::Levels(Levels { levels }))
+                if levels.iter().all(|level| (1..=6).contains(level)) =>
+            {
+                Ok(HeaderLinksSetting::Levels { levels })
+            }
+            _ => Err(de::Error::custom(
+                "header_links must be true, false or {levels: [...]} with levels from 1 to 6",
+            )),
+        }
+    }
+}
+
+// Why the front matter could not be read.
+struct FrontMatterProblem {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+// Splits a line from its `\n` or `\r\n` terminator.
+fn split_ending(line: &str) -> (&str, &str) {
+    let content = line
+        .strip_suffix('\n')
+        .map_or(line, |line| line.strip_suffix('\r').unwrap_or(line));
+    line.split_at(content.len())
+}
+
+fn parse_front_matter(
+    lines: &[&str],
+    kinds: &[BlockKind],
+) -> Result<FrontMatter, FrontMatterProblem> {
+    let yaml = front_matter_yaml(lines, kinds);
+    if yaml.trim().is_empty() {
+        return Ok(FrontMatter::default());
+    }
+    serde_yaml::from_str(&yaml).map_err(|err| {
+        // serde_yaml counts from the line after the opening `---`.
+        let (position, message) = split_yaml_error(&err);
+        let (line, column) = position.unwrap_or((1, 1));
+        FrontMatterProblem {
+            line: line + 1,
+            column,
+            message,
+        }
+    })
+}
+
+// The YAML between the front matter's `---` lines.
+fn front_matter_yaml(lines: &[&str], kinds: &[BlockKind]) -> String {
+    lines
+        .iter()
+        .zip(kinds)
+        .take_while(|(_, &kind)| kind == BlockKind::FrontMatter)
+        .skip(1)
+        .map(|(line, _)| *line)
+        .filter(|line| !matches!(line.trim_end(), "---" | "..."))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The line, column and width of the value of a top-level front matter key,
+// for diagnostics.
+fn front_matter_key_position(
+    lines: &[&str],
+    kinds: &[BlockKind],
+    key: &str,
+) -> (usize, usize, usize) {
+    lines
+        .iter()
+        .zip(kinds)
+        .take_while(|(_, &kind)| kind == BlockKind::FrontMatter)
+        .enumerate()
+        .find_map(|(index, (line, _))| {
+            let value = line.strip_prefix(key)?.strip_prefix(':')?;
+            let key_and_colon = &line[..line.len() - value.trim_start().len()];
+            let column = key_and_colon.chars().count() + 1;
+            Some((index + 1, column, value.trim().chars().count()))
+        })
+        .unwrap_or((1, 1, 1))
+}
+
+// Splits a line into its indentation width (tabs stop every 4 columns) and
+// the remaining text.
+fn split_indent(line: &str) -> (usize, &str) {
+    let mut width = 0;
+    for (offset, c) in line.char_indices() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += 4 - width % 4,
+            _ => return (width, &line[offset..]),
+        }
+    }
+    (width, "")
+}
+
+fn open_fence(rest: &str) -> Option<Fence> {
+    let marker = rest.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = rest.chars().take_while(|&c| c == marker).count();
+    if len < 3 {
+        return None;
+    }
+    // A backtick fence's info string may not itself contain backticks,
+    // otherwise the line is an inline code span.
+    if marker == '`' && rest[len..].contains('`') {
+        return None;
+    }
+    Some(Fence { marker, len })
+}
+
+fn closes_fence(line: &str, open: Fence) -> bool {
+    let (indent, rest) = split_indent(line);
+    let len = rest.chars().take_while(|&c| c == open.marker).count();
+    indent < 4 && len >= open.len && rest[len..].trim().is_empty()
+}
+
+fn open_html_block(rest: &str, in_paragraph: bool) -> Option<HtmlEnd> {
+    if !rest.starts_with('<') {
+        return None;
+    }
+    let lower = rest.to_ascii_lowercase();
+
+    for tag in ["pre", "script", "style", "textarea"] {
+        if let Some(after) = lower.strip_prefix('<').and_then(|s| s.strip_prefix(tag)) {
+            if after.is_empty() || after.starts_with([' ', '\t', '>']) {
+                return Some(HtmlEnd::Terminator(&[
+                    "</pre>",
+                    "</script>",
+                    "</style>",
+                    "</textarea>",
+                ]));
+            }
+        }
+    }
+    if lower.starts_with("<!--") {
+        return Some(HtmlEnd::Terminator(&["-->"]));
+    }
+    if lower.starts_with("<?") {
+        return Some(HtmlEnd::Terminator(&["?>"]));
+    }
+    if lower.starts_with("<![cdata[") {
+        return Some(HtmlEnd::Terminator(&["]]>"]));
+    }
+    if lower[1..].starts_with('!') && lower[2..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Some(HtmlEnd::Terminator(&[">"]));
+    }
+
+    let name_start = if lower.starts_with("</") { 2 } else { 1 };
+    let name_len = lower[name_start..]
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(lower.len() - name_start);
+    let name = &lower[name_start..name_start + name_len];
+    let after = &lower[name_start + name_len..];
+    if HTML_BLOCK_TAGS.contains(&name)
+        && (after.is_empty() || after.starts_with([' ', '\t', '>']) || after.starts_with("/>"))
+    {
+        return Some(HtmlEnd::BlankLine);
+    }
+
+    // Any other complete tag on a line of its own, unless it would interrupt
+    // a paragraph.
+    if !in_paragraph && (HTML_OPEN_TAG_REGEX.is_match(rest) || HTML_CLOSE_TAG_REGEX.is_match(rest))
+    {
+        return Some(HtmlEnd::BlankLine);
+    }
+    None
+}
+
+fn html_block_ends(line: &str, end: HtmlEnd) -> bool {
+    match end {
+        HtmlEnd::Terminator(terminators) => {
+            let lower = line.to_ascii_lowercase();
+            terminators.iter().any(|t| lower.contains(t))
+        }
+        HtmlEnd::BlankLine => line.trim().is_empty(),
+    }
+}
+
+fn is_setext_underline(rest: &str) -> bool {
+    let underline = rest.trim_end();
+    !underline.is_empty()
+        && (underline.bytes().all(|b| b == b'=') || underline.bytes().all(|b| b == b'-'))
+}
+
+// A list item, block quote or table row, none of which can become a setext
+// heading.
+static CONTAINER_START_REGEX: LazyLock<Regex> =
+    static_regex!(r"^(?:[-*+](?:[ \t]|$)|[0-9]{1,9}[.)](?:[ \t]|$)|>|\|)");
+
+fn starts_container(rest: &str) -> bool {
+    CONTAINER_START_REGEX.is_match(rest)
+}
+
+fn is_atx_heading(rest: &str) -> bool {
+    let level = rest.chars().take_while(|&c| c == '#').count();
+    (1..=6).contains(&level) && (rest.len() == level || rest[level..].starts_with([' ', '\t']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BlockKind::*;
+
+    fn kinds(content: &str) -> Vec<BlockKind> {
+        Document::parse(content).kinds
+    }
+
+    #[test]
+    fn headings_only_outside_code_html_and_front_matter() {
+        let content = "---\n\
+                       title: x\n\
+                       ---\n\
+                       # Title\n\
+                       \n\
+                       ```rust\n\
+                       # not a heading\n\
+                       ```\n\
+                       \n\
+                       <div>\n\
+                       # not a heading\n\
+                       </div>\n\
+                       \n\
+                       Setext\n\
+                       ------\n\
+                       \n\
+                       \x20   # indented code\n";
+        assert_eq!(
+            kinds(content),
+            [
+                FrontMatter,
+                FrontMatter,
+                FrontMatter,
+                Heading,
+                Blank,
+                FencedCode,
+                FencedCode,
+                FencedCode,
+                Blank,
+                HtmlBlock,
+                HtmlBlock,
+                HtmlBlock,
+                Blank,
+                SetextHeading,
+                SetextUnderline,
+                Blank,
+                IndentedCode,
+            ]
+        );
+    }
+
+    #[test]
+    fn fences_close_with_at_least_as_many_markers() {
+        let content = "````\n```\n# inside\n````\n# outside\n";
+        assert_eq!(
+            kinds(content),
+            [FencedCode, FencedCode, FencedCode, FencedCode, Heading]
+        );
+        let content = "~~~\n```\n~~~\n";
+        assert_eq!(kinds(content), [FencedCode, FencedCode, FencedCode]);
+    }
+
+    #[test]
+    fn atx_headings_need_a_space() {
+        assert_eq!(kinds("#hashtag\n"), [Paragraph]);
+        assert_eq!(kinds("####### seven\n"), [Paragraph]);
+        assert_eq!(kinds("   ## indented\n"), [Heading]);
+        assert_eq!(kinds("##\n"), [Heading]);
+    }
+
+    #[test]
+    fn indented_lines_continue_a_paragraph() {
+        assert_eq!(kinds("text\n    more text\n"), [Paragraph, Paragraph]);
+    }
+
+    #[test]
+    fn setext_headings() {
+        assert_eq!(
+            kinds("Two\nlines\n===\n"),
+            [SetextHeading, SetextHeading, SetextUnderline]
+        );
+        // Under a list item `---` is a thematic break, not an underline.
+        assert_ne!(kinds("- item\n---\n")[1], SetextUnderline);
+        assert_eq!(kinds("\n---\n"), [Blank, Paragraph]);
+    }
+
+    #[test]
+    fn html_blocks_end_where_commonmark_says() {
+        assert_eq!(
+            kinds("<!--\n# a\n-->\n# b\n"),
+            [HtmlBlock, HtmlBlock, HtmlBlock, Heading]
+        );
+        assert_eq!(
+            kinds("<pre>\n\n# a\n</pre>\n"),
+            [HtmlBlock, HtmlBlock, HtmlBlock, HtmlBlock]
+        );
+        // An inline tag cannot interrupt a paragraph.
+        assert_eq!(kinds("text\n<span>\n"), [Paragraph, Paragraph]);
+    }
+
+    #[test]
+    fn blocks_group_lines() {
+        let document = Document::parse("# A\n\n\ntext\nmore\n```\n```\n```\n```\n");
+        let blocks: Vec<(BlockKind, Range<usize>)> = document
+            .blocks()
+            .into_iter()
+            .map(|block| (block.kind, block.lines))
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                (Heading, 0..1),
+                (Blank, 1..3),
+                (Paragraph, 3..5),
+                (FencedCode, 5..7),
+                (FencedCode, 7..9),
+            ]
+        );
+    }
+
+    #[test]
+    fn untouched_content_round_trips_byte_for_byte() {
+        for content in [
+            "",
+            "# A\n",
+            "# A",
+            "a\r\nb\r\n",
+            "mixed\r\nendings\n",
+            "\u{feff}# A\nb",
+            "trailing blank lines\n\n\n",
+        ] {
+            let document = Document::parse(content);
+            let lines: Vec<String> = document.lines.iter().map(|line| line.to_string()).collect();
+            assert_eq!(document.reassemble(&lines), content);
+        }
+    }
+
+    #[test]
+    fn splice_uses_the_files_line_endings() {
+        let document = Document::parse("a\r\nb\r\nc");
+        let replacement = ["x".to_string(), "y".to_string()];
+        assert_eq!(document.splice(1..2, &replacement), "a\r\nx\r\ny\r\nc");
+        let document = Document::parse("a");
+        assert_eq!(document.splice(1..1, &["b".to_string()]), "a\nb\n");
+    }
+
+    #[test]
+    fn front_matter_settings() {
+        let document = Document::parse("---\nheader_links: false\nslugger: gfm\n---\n# A\n");
+        let front_matter = document.front_matter().unwrap();
+        assert_eq!(
+            front_matter.header_links,
+            Some(HeaderLinksSetting::Enabled(false))
+        );
+        assert_eq!(front_matter.slugger.as_deref(), Some("gfm"));
+
+        let document = Document::parse("---\nheader_links:\n  levels: [2, 3]\n---\n");
+        assert_eq!(
+            document.front_matter().unwrap().header_links,
+            Some(HeaderLinksSetting::Levels { levels: vec![2, 3] })
+        );
+    }
+
+    #[test]
+    fn malformed_settings_are_errors() {
+        for setting in [
+            "header_links: no",
+            "header_links: {levels: 2}",
+            "header_links: {levels: [7]}",
+            "header_links: {level: [2]}",
+            "header_links: [",
+        ] {
+            let content = format!("---\n{}\n---\n## A\n", setting);
+            let document = Document::parse(&content);
+            assert!(document.warnings().is_empty());
+            let Err(Error::MalformedFrontMatter {
+                line: 2 | 3,
+                message,
+                ..
+            }) = document.front_matter()
+            else {
+                panic!("`{}` was accepted", setting);
+            };
+            assert!(!message.contains("untagged"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn problems_become_warnings() {
+        let document = Document::parse("---\nslugger: nope\n---\n\n  ```\ncode\n");
+        assert!(matches!(
+            document.warnings(),
+            [
+                Error::UnknownSlugger {
+                    line: 2,
+                    column: 10,
+                    ..
+                },
+                Error::UnclosedFence {
+                    line: 5,
+                    column: 3,
+                    ..
+                },
+            ]
+        ));
+    }
+}
@@ -0,0 +1,372 @@
+use regex::Regex;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Something wrong with a document, the settings or the files around them.
+/// Lines and columns are 1-based; columns count characters, and `width` is
+/// how many of them, from the column on, the problem is about.
+#[derive(Debug)]
+pub enum Error {
+    /// Nothing usable is left of the heading text once it is slugged.
+    EmptyAnchor {
+        line: usize,
+        column: usize,
+        width: usize,
+        heading: String,
+    },
+    /// The id is already used by the heading on `first_line`.
+    DuplicateId {
+        line: usize,
+        column: usize,
+        width: usize,
+        id: String,
+        first_line: usize,
+    },
+    /// A code fence that is never closed turns the rest of the document
+    /// into code.
+    UnclosedFence {
+        line: usize,
+        column: usize,
+        width: usize,
+        fence: String,
+    },
+    /// The front matter is not valid YAML, or a link-gen key in it has the
+    /// wrong type. The file is skipped, since its settings are unknown.
+    MalformedFrontMatter {
+        line: usize,
+        column: usize,
+        width: usize,
+        message: String,
+    },
+    /// The front matter names a slugger that does not exist.
+    UnknownSlugger {
+        line: usize,
+        column: usize,
+        width: usize,
+        name: String,
+    },
+    /// A table of contents entry links to an anchor no heading has.
+    BrokenTocEntry {
+        line: usize,
+        column: usize,
+        width: usize,
+        target: String,
+    },
+    /// A heading the table of contents should list but doesn't; `entry` is
+    /// the list item it needs.
+    MissingTocEntry {
+        line: usize,
+        column: usize,
+        width: usize,
+        heading: String,
+        entry: String,
+    },
+    /// A table of contents entry is listed before the entry of a heading
+    /// that comes earlier in the document.
+    TocEntryOrder {
+        line: usize,
+        column: usize,
+        width: usize,
+        target: String,
+        heading_line: usize,
+    },
+    /// A table of contents entry is nested `depth` deep where its heading is
+    /// `expected` deep in the outline; both count from 1.
+    TocEntryNesting {
+        line: usize,
+        column: usize,
+        width: usize,
+        target: String,
+        depth: usize,
+        expected: usize,
+    },
+    /// A link to a fragment nothing in the document has; `suggestion` is the
+    /// closest one that exists.
+    BrokenLink {
+        line: usize,
+        column: usize,
+        width: usize,
+        target: String,
+        suggestion: Option<String>,
+    },
+    /// A link to a page of the site that doesn't exist; `suggestion` is the
+    /// closest URL that does.
+    BrokenSiteLink {
+        line: usize,
+        column: usize,
+        width: usize,
+        url: String,
+        suggestion: Option<String>,
+    },
+    /// A config file that cannot be read or has unknown keys.
+    Config {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+        message: String,
+    },
+    /// A setting that makes no sense, such as an unknown theme.
+    Usage(String),
+    /// The header link markup cannot be matched, e.g. because it is huge.
+    LinkTemplate(regex::Error),
+    Io(io::Error),
+    /// A file that is needed but cannot be read.
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Files cannot be watched for changes.
+    Watch(notify::Error),
+}
+
+/// Errors stop a file from being processed; warnings are only reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Error {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Error::UnclosedFence { .. } | Error::UnknownSlugger { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// A short, stable name for the kind of problem, for editors and scripts.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::EmptyAnchor { .. } => "empty-anchor",
+            Error::DuplicateId { .. } => "duplicate-id",
+            Error::UnclosedFence { .. } => "unclosed-fence",
+            Error::MalformedFrontMatter { .. } => "malformed-front-matter",
+            Error::UnknownSlugger { .. } => "unknown-slugger",
+            Error::BrokenTocEntry { .. } => "broken-toc-entry",
+            Error::MissingTocEntry { .. } => "missing-toc-entry",
+            Error::TocEntryOrder { .. } => "toc-entry-order",
+            Error::TocEntryNesting { .. } => "toc-entry-nesting",
+            Error::BrokenLink { .. } => "broken-link",
+            Error::BrokenSiteLink { .. } => "broken-site-link",
+            Error::Config { .. } => "config",
+            Error::Usage(_) => "usage",
+            Error::LinkTemplate(_) => "link-template",
+            Error::Io(_) | Error::Read { .. } => "io",
+            Error::Watch(_) => "watch",
+        }
+    }
+
+    /// The line and column the problem is at, if it has one.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match *self {
+            Error::EmptyAnchor { line, column, .. }
+            | Error::DuplicateId { line, column, .. }
+            | Error::UnclosedFence { line, column, .. }
+            | Error::MalformedFrontMatter { line, column, .. }
+            | Error::UnknownSlugger { line, column, .. }
+            | Error::BrokenTocEntry { line, column, .. }
+            | Error::MissingTocEntry { line, column, .. }
+            | Error::TocEntryOrder { line, column, .. }
+            | Error::TocEntryNesting { line, column, .. }
+            | Error::BrokenLink { line, column, .. }
+            | Error::BrokenSiteLink { line, column, .. } => Some((line, column)),
+            Error::Config { position, .. } => position,
+            Error::Usage(_)
+            | Error::LinkTemplate(_)
+            | Error::Io(_)
+            | Error::Read { .. }
+            | Error::Watch(_) => None,
+        }
+    }
+
+    /// How many characters from the column to underline; at least one.
+    pub fn width(&self) -> usize {
+        let width = match *self {
+            Error::EmptyAnchor { width, .. }
+            | Error::DuplicateId { width, .. }
+            | Error::UnclosedFence { width, .. }
+            | Error::MalformedFrontMatter { width, .. }
+            | Error::UnknownSlugger { width, .. }
+            | Error::BrokenTocEntry { width, .. }
+            | Error::MissingTocEntry { width, .. }
+            | Error::TocEntryOrder { width, .. }
+            | Error::TocEntryNesting { width, .. }
+            | Error::BrokenLink { width, .. }
+            | Error::BrokenSiteLink { width, .. } => width,
+            _ => 1,
+        };
+        width.max(1)
+    }
+
+    /// The file the problem is in when the error itself knows it.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Config { path, .. } | Error::Read { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// How to fix the problem, when there is an obvious way.
+    pub fn help(&self) -> Option<String> {
+        let help = match self {
+            Error::EmptyAnchor { .. } => {
+                "add an explicit {#id} to the heading or enable transliteration".to_string()
+            }
+            Error::DuplicateId { first_line, .. } => format!(
+                "give this heading or the one on line {} an explicit {{#id}}",
+                first_line
+            ),
+            Error::UnclosedFence { fence, .. } => {
+                format!("close the code block with a line of {}", fence)
+            }
+            Error::MalformedFrontMatter { .. } => {
+                "the file is left unchanged until this is fixed".to_string()
+            }
+            Error::UnknownSlugger { .. } => {
+                "use kramdown, gfm, mdbook or template:<pattern>".to_string()
+            }
+            Error::BrokenTocEntry { .. } => {
+                "link to a heading's anchor, or regenerate the list with `link-gen toc`".to_string()
+            }
+            Error::MissingTocEntry { entry, .. } => {
+                format!("add `{}` to the table of contents", entry)
+            }
+            Error::TocEntryOrder { heading_line, .. } => format!(
+                "its heading is on line {}; list the entries in document order",
+                heading_line
+            ),
+            Error::TocEntryNesting { expected, .. } => format!(
+                "indent it by {} spaces to match the outline",
+                (expected - 1) * 2
+            ),
+            Error::BrokenLink {
+                suggestion: Some(suggestion),
+                ..
+            }
+            | Error::BrokenSiteLink {
+                suggestion: Some(suggestion),
+                ..
+            } => format!("did you mean `{}`?", suggestion),
+            _ => return None,
+        };
+        Some(help)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EmptyAnchor { heading, .. } => {
+                write!(f, "heading `{}` produces an empty anchor", heading)
+            }
+            Error::DuplicateId { id, first_line, .. } => write!(
+                f,
+                "anchor `{}` is already used by the heading on line {}",
+                id, first_line
+            ),
+            Error::UnclosedFence { .. } => write!(f, "code fence is never closed"),
+            Error::MalformedFrontMatter { message, .. } => {
+                write!(f, "malformed front matter: {}", message)
+            }
+            Error::UnknownSlugger { name, .. } => {
+                write!(f, "unknown slugger `{}`, using the default", name)
+            }
+            Error::BrokenTocEntry { target, .. } => write!(
+                f,
+                "table of contents links to `#{}`, which no heading has",
+                target
+            ),
+            Error::MissingTocEntry { heading, .. } => {
+                write!(
+                    f,
+                    "heading `{}` is missing from the table of contents",
+                    heading
+                )
+            }
+            Error::TocEntryOrder { target, .. } => {
+                write!(f, "entry for `#{}` is out of order", target)
+            }
+            Error::TocEntryNesting {
+                target,
+                depth,
+                expected,
+                ..
+            } => write!(
+                f,
+                "entry for `#{}` is nested {} deep, but its heading is {} deep in the outline",
+                target, depth, expected
+            ),
+            Error::BrokenLink { target, .. } => {
+                write!(f, "link to `{}` does not match any anchor", target)
+            }
+            Error::BrokenSiteLink { url, .. } => {
+                write!(f, "link to `{}` does not match any page of the site", url)
+            }
+            Error::Config { message, .. } | Error::Usage(message) => f.write_str(message),
+            Error::LinkTemplate(err) => write!(f, "unusable header link markup: {}", err),
+            Error::Io(err) | Error::Read { source: err, .. } => err.fmt(f),
+            Error::Watch(err) => write!(f, "cannot watch files: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::LinkTemplate(err) => Some(err),
+            Error::Io(err) | Error::Read { source: err, .. } => Some(err),
+            Error::Watch(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// 1-based line and column of a byte offset.
+pub(crate) fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+// A position serde_yaml puts in its messages, counted within the YAML.
+static YAML_POSITION_REGEX: LazyLock<Regex> = static_regex!(r" at line \d+ column \d+");
+
+// serde_yaml puts "at line N column M" into its messages, sometimes twice;
+// the position of the error is reported separately, in file coordinates.
+pub(crate) fn split_yaml_error(err: &serde_yaml::Error) -> (Option<(usize, usize)>, String) {
+    let position = err
+        .location()
+        .map(|location| (location.line(), location.column()));
+    let message = YAML_POSITION_REGEX
+        .replace_all(&err.to_string(), "")
+        .into_owned();
+    (position, message)
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_positions_are_taken_out_of_messages() {
+        let err = serde_yaml::from_str::<serde_yaml::Value>("a: [1, 2").unwrap_err();
+        let (position, message) = split_yaml_error(&err);
+        assert_eq!(position, Some((2, 1)));
+        assert_eq!(
+            message,
+            "did not find expected ',' or ']', while parsing a flow sequence"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Every file below `dir`, skipping hidden files and directories as Jekyll
+/// does, in a stable order.
+pub fn source_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            files.extend(source_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Whether a file is Markdown going by its extension, `.md` or `.markdown`.
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "md" || ext == "markdown")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A fresh directory under the system temp dir, removed when dropped.
+    pub(crate) struct Scratch(pub(crate) PathBuf);
+
+    impl Scratch {
+        pub(crate) fn new(name: &str) -> Scratch {
+            let dir =
+                std::env::temp_dir().join(format!("link-gen-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Scratch(dir)
+        }
+
+        pub(crate) fn write(&self, name: &str, text: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, text).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn finds_files_like_jekyll() {
+        let scratch = Scratch::new("files");
+        scratch.write("b.md", "");
+        scratch.write("a/post.markdown", "");
+        scratch.write("a/image.png", "");
+        scratch.write(".hidden/post.md", "");
+        scratch.write("a/.draft.md", "");
+        let found: Vec<PathBuf> = source_files(&scratch.0)
+            .unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(&scratch.0).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            found,
+            ["a/image.png", "a/post.markdown", "b.md"].map(PathBuf::from)
+        );
+        let markdown: Vec<bool> = found.iter().map(|path| is_markdown(path)).collect();
+        assert_eq!(markdown, [false, true, true]);
+    }
+}
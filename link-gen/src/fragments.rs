@@ -0,0 +1,343 @@
+use crate::anchors::{scan_rendered_headings, Check, HeadingOptions};
+use crate::document::{BlockKind, Document};
+use crate::error::Error;
+use crate::heading::{attribute_id, IAL_LINE_REGEX};
+use crate::site::Site;
+use crate::slug::encode_fragment;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// Settings for one run of `check_links`.
+#[derive(Default)]
+pub struct LinkCheckOptions {
+    /// How anchors are generated; links are checked against the ids the
+    /// headings have as the document is now, with or without header links.
+    pub headings: HeadingOptions,
+    /// The site the document belongs to, for links to other posts. Without
+    /// it only in-page links are checked.
+    pub site: Option<Site>,
+}
+
+// Checks the in-page links and links to the rest of the site.
+impl Check for LinkCheckOptions {
+    fn check(&self, document: &Document) -> Result<Vec<Error>, Error> {
+        check_links(document, self)
+    }
+}
+
+// `[text](url)`, `[text](<url> "title")`.
+static MARKDOWN_LINK_REGEX: LazyLock<Regex> = static_regex!(r"\]\(\s*<?([^)\s>]+)");
+// A reference definition, `[name]: url`.
+static REFERENCE_DEFINITION_REGEX: LazyLock<Regex> =
+    static_regex!(r"^ {0,3}\[[^\]]+\]:\s*<?([^\s>]+)");
+// `<a href="url">` and other HTML links.
+static HTML_LINK_REGEX: LazyLock<Regex> = static_regex!(r#"\bhref\s*=\s*["']([^"']+)["']"#);
+// Ids that HTML elements give themselves.
+static HTML_ID_REGEX: LazyLock<Regex> = static_regex!(r#"\b(?:id|name)\s*=\s*["']([^"']+)["']"#);
+// A footnote reference or definition, `[^name]`.
+static FOOTNOTE_REGEX: LazyLock<Regex> = static_regex!(r"\[\^([^\]\s]+)\]");
+// An inline code span; links inside it are just text.
+static CODE_SPAN_REGEX: LazyLock<Regex> = static_regex!(r"``[^`](?:.*?[^`])?``|`[^`]+`");
+
+/// Checks the links of a document: every `[text](#fragment)`, reference
+/// definition and `<a href="#...">` against the anchors the document has
+/// (heading ids, `{: #id}` attribute lists, HTML `id`s and kramdown's
+/// footnote ids), and, given the site, every link to one of its pages
+/// against the site's permalinks and that page's anchors. Dangling links
+/// come with the closest existing page or anchor as a suggestion.
+pub fn check_links(document: &Document, options: &LinkCheckOptions) -> Result<Vec<Error>, Error> {
+    let anchors = document_anchors(document, &options.headings)?;
+    let mut problems = Vec::new();
+    for (line, target) in link_targets(document) {
+        if let Some(fragment) = target.text.strip_prefix('#') {
+            // A bare `#` links to the top of the page.
+            if fragment.is_empty()
+                || anchors
+                    .iter()
+                    .any(|anchor| matches_anchor(fragment, anchor))
+            {
+                continue;
+            }
+            problems.push(Error::BrokenLink {
+                line: line + 1,
+                column: target.column,
+                width: target.text.chars().count(),
+                target: target.text.to_string(),
+                suggestion: closest(fragment, &anchors).map(|anchor| format!("#{}", anchor)),
+            });
+            continue;
+        }
+
+        let Some(site) = &options.site else {
+            continue;
+        };
+        let Some(path) = site.site_path(target.text) else {
+            continue;
+        };
+        let fragment = target.text.split_once('#').map(|(_, fragment)| fragment);
+        match site.page(path) {
+            None => problems.push(Error::BrokenSiteLink {
+                line: line + 1,
+                column: target.column,
+                width: target.text.chars().count(),
+                url: target.text.to_string(),
+                suggestion: closest(path, site.urls()).map(|url| {
+                    let fragment = fragment.map(|f| format!("#{}", f)).unwrap_or_default();
+                    format!("{}{}", site.link_to(url), fragment)
+                }),
+            }),
+            Some(Some(page_anchors)) => {
+                let Some(fragment) = fragment.filter(|fragment| !fragment.is_empty()) else {
+                    continue;
+                };
+                if page_anchors
+                    .iter()
+                    .any(|anchor| matches_anchor(fragment, anchor))
+                {
+                    continue;
+                }
+                let page = &target.text[..target.text.len() - fragment.len() - 1];
+                problems.push(Error::BrokenLink {
+                    line: line + 1,
+                    column: target.column,
+                    width: target.text.chars().count(),
+                    target: target.text.to_string(),
+                    suggestion: closest(fragment, page_anchors)
+                        .map(|anchor| format!("{}#{}", page, anchor)),
+                });
+            }
+            // A page whose anchors aren't known, or a static file.
+            Some(None) => {}
+        }
+    }
+    Ok(problems)
+}
+
+// Every id a fragment in the document can point to.
+pub(crate) fn document_anchors(
+    document: &Document,
+    options: &HeadingOptions,
+) -> Result<BTreeSet<String>, Error> {
+    let mut anchors: BTreeSet<String> = scan_rendered_headings(document, options)?
+        .into_iter()
+        .map(|scanned| scanned.heading.anchor)
+        .collect();
+    for (_, line) in prose_lines(document) {
+        if IAL_LINE_REGEX.is_match(line.trim()) {
+            anchors.extend(attribute_id(line.trim()).map(str::to_string));
+        }
+        for id in HTML_ID_REGEX.captures_iter(line).filter_map(|c| c.get(1)) {
+            anchors.insert(id.as_str().to_string());
+        }
+        for name in FOOTNOTE_REGEX.captures_iter(line).filter_map(|c| c.get(1)) {
+            anchors.insert(format!("fn:{}", name.as_str()));
+            anchors.insert(format!("fnref:{}", name.as_str()));
+        }
+    }
+    Ok(anchors)
+}
+
+// A link target as written, and the 1-based column it starts at.
+struct LinkTarget<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+// Every link target in the prose of a document, outside code, with the
+// (0-based) line it is on.
+fn link_targets<'a>(document: &Document<'a>) -> Vec<(usize, LinkTarget<'a>)> {
+    let mut targets = Vec::new();
+    for (index, line) in prose_lines(document) {
+        let code_spans: Vec<Range<usize>> = CODE_SPAN_REGEX
+            .find_iter(line)
+            .map(|span| span.range())
+            .collect();
+        let found = [
+            &*MARKDOWN_LINK_REGEX,
+            &*REFERENCE_DEFINITION_REGEX,
+            &*HTML_LINK_REGEX,
+        ]
+        .into_iter()
+        .flat_map(|regex| regex.captures_iter(line).filter_map(|c| c.get(1)));
+        let mut on_line: Vec<_> = found
+            .filter(|target| !code_spans.iter().any(|span| span.contains(&target.start())))
+            .collect();
+        on_line.sort_by_key(|target| target.start());
+        for target in on_line {
+            targets.push((
+                index,
+                LinkTarget {
+                    text: target.as_str(),
+                    column: line[..target.start()].chars().count() + 1,
+                },
+            ));
+        }
+    }
+    targets
+}
+
+// Lines that can hold links: everything but code and the front matter.
+fn prose_lines<'a, 'd>(document: &'d Document<'a>) -> impl Iterator<Item = (usize, &'a str)> + 'd {
+    document
+        .lines
+        .iter()
+        .zip(&document.kinds)
+        .enumerate()
+        .filter(|(_, (_, kind))| {
+            !matches!(
+                kind,
+                BlockKind::FencedCode | BlockKind::IndentedCode | BlockKind::FrontMatter
+            )
+        })
+        .map(|(index, (&line, _))| (index, line))
+}
+
+// Links may percent-encode what the anchor spells out.
+fn matches_anchor(fragment: &str, anchor: &str) -> bool {
+    fragment == anchor || fragment == encode_fragment(anchor)
+}
+
+// The anchor or URL a mistyped one most likely meant: the nearest by edit
+// distance, if no more than a third of it (or two characters) has to
+// change.
+fn closest<'a>(
+    mistyped: &str,
+    candidates: impl IntoIterator<Item = &'a String>,
+) -> Option<&'a str> {
+    let limit = (mistyped.chars().count() / 3).max(2);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(mistyped, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+// Levenshtein distance, counting characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anchors::process_markdown_headings;
+
+    fn check(content: &str) -> Vec<Error> {
+        check_links(&Document::parse(content), &LinkCheckOptions::default()).unwrap()
+    }
+
+    // The broken targets, with their line and column and suggestion.
+    fn broken(content: &str) -> Vec<(usize, usize, String, Option<String>)> {
+        check(content)
+            .into_iter()
+            .map(|problem| match problem {
+                Error::BrokenLink {
+                    line,
+                    column,
+                    target,
+                    suggestion,
+                    ..
+                } => (line, column, target, suggestion),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn headings_without_links() {
+        assert!(check("## Intro\n\nSee [x](#intro).\n").is_empty());
+        assert_eq!(
+            broken("## Intro\n\nSee [x](#intro-).\n"),
+            [(3, 9, "#intro-".to_string(), Some("#intro".to_string()))]
+        );
+    }
+
+    #[test]
+    fn headings_with_links() {
+        let content = "## Intro\n\n## Intro\n\nSee [x](#intro-), [y](#intro--1) and [z](#intro).\n";
+        let linked = process_markdown_headings(&Document::parse(content), &Default::default())
+            .unwrap()
+            .content;
+        assert_eq!(
+            broken(&linked),
+            [(5, 42, "#intro".to_string(), Some("#intro-".to_string()))]
+        );
+    }
+
+    #[test]
+    fn links_in_code_are_text() {
+        let content = "# Title\n\n\
+                       Use `[x](#nope)` or ``[y](#nope)``, not [z](#nope).\n\n\
+                       ```md\n[x](#nope)\n```\n\n    [x](#nope)\n";
+        assert_eq!(broken(content), [(3, 45, "#nope".to_string(), None)]);
+    }
+
+    #[test]
+    fn reference_definitions_and_html_links() {
+        let content = "# Title\n\n[x][ref] and <a href=\"#titel\">y</a>.\n\n[ref]: #tilte\n";
+        assert_eq!(
+            broken(content),
+            [
+                (3, 23, "#titel".to_string(), Some("#title".to_string())),
+                (5, 8, "#tilte".to_string(), Some("#title".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_anchors() {
+        let content = "# Title\n\n\
+                       <a id=\"here\"></a> A note[^1].\n\
+                       {: #para}\n\n\
+                       [a](#here) [b](#para) [c](#fn:1) [d](#fnref:1) [e](#) [f](#fn:2)\n\n\
+                       [^1]: The note.\n";
+        assert_eq!(
+            broken(content),
+            [(6, 59, "#fn:2".to_string(), Some("#fn:1".to_string()))]
+        );
+    }
+
+    #[test]
+    fn percent_encoded_fragments() {
+        // GitHub keeps the accents that kramdown drops.
+        let content =
+            "---\nslugger: gfm\n---\n## Déjà vu\n\n[a](#déjà-vu) [b](#d%C3%A9j%C3%A0-vu)\n";
+        assert!(check(content).is_empty());
+    }
+
+    #[test]
+    fn closest_candidate() {
+        let candidates: Vec<String> = ["introduction", "intro", "summary"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(closest("intr", &candidates), Some("intro"));
+        assert_eq!(closest("introductoin", &candidates), Some("introduction"));
+        // At most two edits for short names, a third of longer ones.
+        assert_eq!(closest("sumary", &candidates), Some("summary"));
+        assert_eq!(closest("smry", &candidates), None);
+        assert_eq!(closest("conclusion", &candidates), None);
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", "abc"), 0);
+        assert_eq!(edit_distance("café", "cafe"), 1);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::document::BlockKind;
+use crate::link::strip_header_link;
+use regex::Regex;
+use std::sync::LazyLock;
+
+// A heading, split around the text the header link is added to:
+// `{prefix}{text}{closing}{attributes}`.
+pub(crate) struct HeadingLine<'a> {
+    // Index of the line that holds the end of the heading text.
+    pub(crate) line: usize,
+    pub(crate) level: usize,
+    // Indentation and, for ATX headings, the opening `#`s and spacing.
+    pub(crate) prefix: &'a str,
+    // Earlier lines of a multi-line setext heading.
+    pub(crate) leading_lines: Vec<&'a str>,
+    // The heading text on `line`, without any previous header link.
+    pub(crate) text: &'a str,
+    // An ATX closing sequence such as ` ##`.
+    pub(crate) closing: &'a str,
+    // A trailing `{#id}` or `{: ...}` attribute list.
+    pub(crate) attributes: Option<&'a str>,
+}
+
+impl HeadingLine<'_> {
+    pub(crate) fn full_text(&self) -> String {
+        let mut text = self.leading_lines.join(" ");
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(self.text);
+        text
+    }
+}
+
+pub(crate) static ATX_OPENING_REGEX: LazyLock<Regex> =
+    static_regex!(r"^ {0,3}(#{1,6})(?:[ \t]+|$)");
+pub(crate) static ATX_CLOSING_REGEX: LazyLock<Regex> = static_regex!(r"(?:^|[ \t]+)#+[ \t]*$");
+
+// Splits an ATX heading (`## Title ##`, indented by up to three spaces).
+pub(crate) fn parse_atx_heading<'a>(
+    line: &'a str,
+    index: usize,
+    link_regex: &Regex,
+) -> Option<HeadingLine<'a>> {
+    let opening = ATX_OPENING_REGEX.captures(line)?;
+    let prefix_len = opening.get(0)?.end();
+    let level = opening.get(1)?.len();
+    let content = line[prefix_len..].trim_end();
+
+    // An explicit id, `{#id}` or `{: #id .class}`, has to stay at the very
+    // end of the line, after any closing sequence.
+    let (content, attributes) = split_attributes(content);
+    let (content, closing) = match ATX_CLOSING_REGEX.find(content) {
+        Some(found) => (&content[..found.start()], &content[found.start()..]),
+        None => (content, ""),
+    };
+    // Drop the link a previous run appended so re-runs replace it instead of
+    // stacking a second one, and a renamed heading gets a fresh anchor.
+    let text = strip_header_link(content, link_regex);
+    if text.is_empty() {
+        return None;
+    }
+
+    // Keep a single space between the `#`s and the text.
+    let prefix = line[..prefix_len].trim_end();
+    Some(HeadingLine {
+        line: index,
+        level,
+        prefix: &line[..prefix.len() + 1],
+        leading_lines: Vec::new(),
+        text,
+        closing,
+        attributes,
+    })
+}
+
+// Collects the text lines above a setext underline (`===` or `---`).
+pub(crate) fn parse_setext_heading<'a>(
+    lines: &[&'a str],
+    kinds: &[BlockKind],
+    underline: usize,
+    link_regex: &Regex,
+) -> Option<HeadingLine<'a>> {
+    let first = (0..underline)
+        .rev()
+        .take_while(|&index| kinds[index] == BlockKind::SetextHeading)
+        .last()?;
+    let last = underline - 1;
+    let line = lines[last];
+
+    let (indent, content) = line.split_at(line.len() - line.trim_start().len());
+    let (content, attributes) = split_attributes(content.trim_end());
+    let text = strip_header_link(content, link_regex);
+    if text.is_empty() {
+        return None;
+    }
+
+    let level = if lines[underline].trim_start().starts_with('=') {
+        1
+    } else {
+        2
+    };
+    Some(HeadingLine {
+        line: last,
+        level,
+        prefix: indent,
+        leading_lines: lines[first..last].iter().map(|line| line.trim()).collect(),
+        text,
+        closing: "",
+        attributes,
+    })
+}
+
+// A kramdown header id (`{#id}`) or inline attribute list (`{: ...}`) at the
+// end of a heading.
+pub(crate) static TRAILING_ATTRIBUTES_REGEX: LazyLock<Regex> =
+    static_regex!(r"[\t ]+(\{#[A-Za-z][\w:-]*\}|\{:[^{}]*\})\s*$");
+// A block inline attribute list on a line of its own.
+pub(crate) static IAL_LINE_REGEX: LazyLock<Regex> = static_regex!(r"^\{:[^{}]*\}$");
+pub(crate) static ATTRIBUTE_ID_REGEX: LazyLock<Regex> =
+    static_regex!(r"(?:^|[\s{:])#([A-Za-z][\w:-]*)");
+
+// Splits heading text from a trailing `{#id}` or `{: ...}` attribute list.
+pub(crate) fn split_attributes(heading_text: &str) -> (&str, Option<&str>) {
+    let found = TRAILING_ATTRIBUTES_REGEX
+        .captures(heading_text)
+        .and_then(|captures| Some((captures.get(0)?, captures.get(1)?)));
+    match found {
+        Some((whole, attributes)) => (&heading_text[..whole.start()], Some(attributes.as_str())),
+        None => (heading_text, None),
+    }
+}
+
+// The `#id` inside an attribute list, if it sets one.
+pub(crate) fn attribute_id(attributes: &str) -> Option<&str> {
+    ATTRIBUTE_ID_REGEX
+        .captures(attributes)
+        .and_then(|captures| captures.get(1))
+        .map(|id| id.as_str())
+}
+
+// Adds `#id` to an attribute list that only sets classes or other attributes.
+pub(crate) fn pin_attribute_id(attributes: &str, id: &str) -> String {
+    let inner = attributes
+        .strip_prefix("{:")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(attributes)
+        .trim();
+    format!("{{: #{} {}}}", id, inner)
+}
+
+// Whether an attribute list gives the heading a class, such as `.no_toc`.
+pub(crate) fn has_class(attributes: &str, class: &str) -> bool {
+    attributes
+        .trim_start_matches("{:")
+        .trim_end_matches('}')
+        .split_whitespace()
+        .any(|attribute| attribute.strip_prefix('.') == Some(class))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::classify_lines;
+    use crate::link::LinkTemplate;
+
+    fn link_regex() -> Regex {
+        LinkTemplate::default().link_regex().unwrap()
+    }
+
+    #[test]
+    fn atx_heading_parts() {
+        let heading = parse_atx_heading("  ##   Title ##   {#custom}", 4, &link_regex()).unwrap();
+        assert_eq!(heading.line, 4);
+        assert_eq!(heading.level, 2);
+        assert_eq!(heading.prefix, "  ## ");
+        assert_eq!(heading.text, "Title");
+        assert_eq!(heading.closing, " ##");
+        assert_eq!(heading.attributes, Some("{#custom}"));
+    }
+
+    #[test]
+    fn atx_closing_sequences() {
+        let regex = link_regex();
+        // `#` inside a word is text, not a closing sequence.
+        let heading = parse_atx_heading("## C#", 0, &regex).unwrap();
+        assert_eq!((heading.text, heading.closing), ("C#", ""));
+        let heading = parse_atx_heading("# Title #####  ", 0, &regex).unwrap();
+        assert_eq!((heading.text, heading.closing), ("Title", " #####"));
+        assert!(parse_atx_heading("### ###", 0, &regex).is_none());
+        assert!(parse_atx_heading("#hashtag", 0, &regex).is_none());
+    }
+
+    #[test]
+    fn previous_header_links_are_dropped() {
+        let line = r##"## Title <a href="#title-" class="header-link">🔗</a>"##;
+        let heading = parse_atx_heading(line, 0, &link_regex()).unwrap();
+        assert_eq!(heading.text, "Title");
+        let line = r##"## <a href="#title" class="header-link">Title</a> {: .x}"##;
+        let heading = parse_atx_heading(line, 0, &link_regex()).unwrap();
+        assert_eq!(heading.text, "Title");
+        assert_eq!(heading.attributes, Some("{: .x}"));
+    }
+
+    #[test]
+    fn setext_heading_parts() {
+        let lines = ["A heading", "  over two lines {#two}", "---"];
+        let (kinds, _) = classify_lines(&lines);
+        let heading = parse_setext_heading(&lines, &kinds, 2, &link_regex()).unwrap();
+        assert_eq!(heading.line, 1);
+        assert_eq!(heading.level, 2);
+        assert_eq!(heading.prefix, "  ");
+        assert_eq!(heading.leading_lines, ["A heading"]);
+        assert_eq!(heading.text, "over two lines");
+        assert_eq!(heading.attributes, Some("{#two}"));
+        assert_eq!(heading.full_text(), "A heading over two lines");
+
+        let lines = ["Title", "==="];
+        let (kinds, _) = classify_lines(&lines);
+        let heading = parse_setext_heading(&lines, &kinds, 1, &link_regex()).unwrap();
+        assert_eq!((heading.level, heading.text), (1, "Title"));
+    }
+
+    #[test]
+    fn attribute_lists() {
+        assert_eq!(
+            split_attributes("Title {: .no_toc}"),
+            ("Title", Some("{: .no_toc}"))
+        );
+        assert_eq!(split_attributes("Title {#id}"), ("Title", Some("{#id}")));
+        // Not a valid id, so part of the text.
+        assert_eq!(split_attributes("Set {#1}"), ("Set {#1}", None));
+        assert_eq!(attribute_id("{: #intro .x}"), Some("intro"));
+        assert_eq!(attribute_id("{#intro}"), Some("intro"));
+        assert_eq!(attribute_id("{: .x}"), None);
+        assert_eq!(
+            pin_attribute_id("{: .no_toc}", "intro"),
+            "{: #intro .no_toc}"
+        );
+        assert!(has_class("{: .no_toc .x}", "no_toc"));
+        assert!(!has_class("{: .no_tocs}", "no_toc"));
+    }
+}
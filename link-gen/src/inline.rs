@@ -0,0 +1,357 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+static INLINE_HTML_REGEX: LazyLock<Regex> = static_regex!(
+    r#"^(?:<[A-Za-z][A-Za-z0-9-]*(?:\s+[A-Za-z_:][A-Za-z0-9_.:-]*(?:\s*=\s*(?:[^\s"'=<>`]+|'[^']*'|"[^"]*"))?)*\s*/?>|</[A-Za-z][A-Za-z0-9-]*\s*>|<!--.*?-->)"#
+);
+static AUTOLINK_REGEX: LazyLock<Regex> = static_regex!(
+    r"^<([A-Za-z][A-Za-z0-9+.-]{1,31}:[^\s<>]*|[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]+@[A-Za-z0-9][A-Za-z0-9.-]*)>"
+);
+static ENTITY_REGEX: LazyLock<Regex> =
+    static_regex!(r"^&(?:#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[A-Za-z][A-Za-z0-9]{1,31});");
+
+/// Renders the inline Markdown of a heading (emphasis, code spans, links,
+/// images, inline HTML, escapes and entities) to the plain text a reader
+/// sees, which is what the renderers derive ids from.
+pub fn heading_plain_text(heading_text: &str) -> String {
+    let mut pieces = Vec::new();
+    let mut plain = String::with_capacity(heading_text.len());
+    let text = heading_text;
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        match bytes[i] {
+            b'\\' if rest[1..].starts_with(|c: char| c.is_ascii_punctuation()) => {
+                plain.push(bytes[i + 1] as char);
+                i += 2;
+                continue;
+            }
+            b'`' => {
+                let run = run_length(rest, b'`');
+                if let Some(close) = find_code_span_end(&rest[run..], run) {
+                    let code = &rest[run..run + close];
+                    // One space of padding on each side is not part of the code.
+                    let code = match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                        Some(inner) if !inner.trim().is_empty() => inner,
+                        _ => code,
+                    };
+                    plain.push_str(code);
+                    i += run + close + run;
+                } else {
+                    plain.push_str(&rest[..run]);
+                    i += run;
+                }
+                continue;
+            }
+            b'!' if rest[1..].starts_with('[') => {
+                // Images render no text.
+                if let Some((_, end)) = parse_link(&rest[1..]) {
+                    i += 1 + end;
+                    continue;
+                }
+            }
+            b'[' => {
+                if let Some((label, end)) = parse_link(rest) {
+                    // Emphasis cannot reach into or out of a link.
+                    plain.push_str(&heading_plain_text(label));
+                    i += end;
+                    continue;
+                }
+            }
+            b'<' => {
+                if let Some(captures) = AUTOLINK_REGEX.captures(rest) {
+                    plain.push_str(&captures[1]);
+                    i += captures[0].len();
+                    continue;
+                }
+                if let Some(tag) = INLINE_HTML_REGEX.find(rest) {
+                    i += tag.end();
+                    continue;
+                }
+            }
+            b'&' => {
+                if let Some(entity) = ENTITY_REGEX.find(rest) {
+                    if let Some(decoded) = decode_entity(entity.as_str()) {
+                        plain.push(decoded);
+                        i += entity.end();
+                        continue;
+                    }
+                }
+            }
+            delimiter @ (b'*' | b'_' | b'~') => {
+                let run = run_length(rest, delimiter);
+                let before = text[..i].chars().next_back();
+                let after = rest[run..].chars().next();
+                let (can_open, can_close) = flanking(delimiter, run, before, after);
+                pieces.push(Piece::Text(std::mem::take(&mut plain)));
+                pieces.push(Piece::Delimiters(DelimiterRun {
+                    delimiter,
+                    len: run,
+                    left: run,
+                    can_open,
+                    can_close,
+                }));
+                i += run;
+                continue;
+            }
+            _ => {}
+        }
+        let Some(c) = rest.chars().next() else {
+            break;
+        };
+        plain.push(c);
+        i += c.len_utf8();
+    }
+    pieces.push(Piece::Text(plain));
+
+    match_emphasis(&mut pieces);
+    let mut rendered = String::with_capacity(heading_text.len());
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => rendered.push_str(&text),
+            Piece::Delimiters(run) => {
+                rendered.extend(std::iter::repeat_n(run.delimiter as char, run.left));
+            }
+        }
+    }
+    rendered
+}
+
+// Inline text split around runs of `*`, `_` and `~`, which only become
+// emphasis if they pair up.
+enum Piece {
+    Text(String),
+    Delimiters(DelimiterRun),
+}
+
+struct DelimiterRun {
+    delimiter: u8,
+    len: usize,
+    // The delimiters not used up by emphasis, which stay as text.
+    left: usize,
+    can_open: bool,
+    can_close: bool,
+}
+
+// CommonMark's "process emphasis": each closing run is matched with the
+// nearest opening run before it, and the delimiters they use up are
+// dropped. Unmatched delimiters, as in `_private field`, are literal text.
+fn match_emphasis(pieces: &mut [Piece]) {
+    for closer in 0..pieces.len() {
+        while let Piece::Delimiters(close) = &pieces[closer] {
+            if !close.can_close || close.left == 0 {
+                break;
+            }
+            let (delimiter, close_len, close_left, close_can_open) =
+                (close.delimiter, close.len, close.left, close.can_open);
+            let opener = (0..closer).rev().find(|&index| match &pieces[index] {
+                Piece::Delimiters(open) => {
+                    open.delimiter == delimiter
+                        && open.can_open
+                        && open.left > 0
+                        // Strikethrough needs runs of the same length.
+                        && (delimiter != b'~' || open.left == close_left)
+                        // The "multiple of 3" rule for runs that can both
+                        // open and close, as in `*foo**bar*`.
+                        && !((open.can_close || close_can_open)
+                            && (open.len + close_len) % 3 == 0
+                            && !(open.len % 3 == 0 && close_len % 3 == 0))
+                }
+                Piece::Text(_) => false,
+            });
+            let Some(opener) = opener else {
+                break;
+            };
+            let Piece::Delimiters(open) = &pieces[opener] else {
+                break;
+            };
+            let used = if open.left >= 2 && close_left >= 2 {
+                2
+            } else {
+                1
+            };
+            for (index, piece) in pieces.iter_mut().enumerate().take(closer + 1).skip(opener) {
+                if let Piece::Delimiters(run) = piece {
+                    if index == opener || index == closer {
+                        run.left -= used;
+                    } else {
+                        // Runs inside the emphasis can no longer pair with
+                        // runs outside it.
+                        run.can_open = false;
+                        run.can_close = false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn run_length(text: &str, byte: u8) -> usize {
+    text.bytes().take_while(|&b| b == byte).count()
+}
+
+// Finds the backtick run of exactly `run` characters closing a code span,
+// returning the length of the code in between.
+fn find_code_span_end(text: &str, run: usize) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find('`') {
+        let start = offset + start;
+        let len = run_length(&text[start..], b'`');
+        if len == run {
+            return Some(start);
+        }
+        offset = start + len;
+    }
+    None
+}
+
+// Parses `[label](destination)`, `[label][ref]` or `[label][]` at the start
+// of `text`, returning the label and the length of the whole link.
+fn parse_link(text: &str) -> Option<(&str, usize)> {
+    let label_end = find_closing(text, b'[', b']')?;
+    let label = &text[1..label_end];
+    let after = &text[label_end + 1..];
+    let tail = match after.as_bytes().first()? {
+        b'(' => find_closing(after, b'(', b')')?,
+        b'[' => find_closing(after, b'[', b']')?,
+        _ => return None,
+    };
+    Some((label, label_end + 1 + tail + 1))
+}
+
+// Index of the bracket matching the one `text` starts with, skipping
+// escaped and nested brackets.
+fn find_closing(text: &str, open: u8, close: u8) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b if b == open => depth += 1,
+            b if b == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+// CommonMark's flanking rules, which decide whether a run of `*`, `_` or
+// `~~` can open and whether it can close emphasis.
+fn flanking(delimiter: u8, run: usize, before: Option<char>, after: Option<char>) -> (bool, bool) {
+    if delimiter == b'~' && run != 2 {
+        return (false, false);
+    }
+    let is_space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+    let is_punct = |c: Option<char>| c.is_some_and(|c| !c.is_alphanumeric() && !c.is_whitespace());
+    let left_flanking =
+        !is_space(after) && (!is_punct(after) || is_space(before) || is_punct(before));
+    let right_flanking =
+        !is_space(before) && (!is_punct(before) || is_space(after) || is_punct(after));
+    if delimiter == b'_' {
+        // Underscores inside a word (`snake_case`) are literal.
+        (
+            left_flanking && (!right_flanking || is_punct(before)),
+            right_flanking && (!left_flanking || is_punct(after)),
+        )
+    } else {
+        (left_flanking, right_flanking)
+    }
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    let name = &entity[1..entity.len() - 1];
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code)
+            .filter(|&c| c != '\0')
+            .or(Some('\u{FFFD}'));
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{A0}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "rarr" => '→',
+        "larr" => '←',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(heading_plain_text("**Bold** and *em*"), "Bold and em");
+        assert_eq!(heading_plain_text("__Bold__ and _em_"), "Bold and em");
+        assert_eq!(heading_plain_text("~~gone~~ here"), "gone here");
+        // Delimiters that cannot open or close emphasis are text.
+        assert_eq!(heading_plain_text("snake_case_name"), "snake_case_name");
+        assert_eq!(heading_plain_text("2 * 3 * 4"), "2 * 3 * 4");
+        assert_eq!(heading_plain_text("~tilde~"), "~tilde~");
+        // Delimiters that never pair up are text too.
+        assert_eq!(heading_plain_text("_private field"), "_private field");
+        assert_eq!(heading_plain_text("**unclosed bold"), "**unclosed bold");
+        assert_eq!(heading_plain_text("*a **b** c*"), "a b c");
+        assert_eq!(heading_plain_text("***both***"), "both");
+        assert_eq!(heading_plain_text("**bold*"), "*bold");
+        assert_eq!(heading_plain_text("*em* and *"), "em and *");
+        assert_eq!(
+            crate::slug::generate_gfm_anchor(&heading_plain_text("_private field")),
+            "_private-field"
+        );
+    }
+
+    #[test]
+    fn code_spans() {
+        assert_eq!(heading_plain_text("`Vec<T>` in `std`"), "Vec<T> in std");
+        assert_eq!(heading_plain_text("`` a`b ``"), "a`b");
+        assert_eq!(heading_plain_text("`*not em*`"), "*not em*");
+        assert_eq!(heading_plain_text("unclosed `tick"), "unclosed `tick");
+    }
+
+    #[test]
+    fn links_and_images() {
+        assert_eq!(heading_plain_text("[Link](http://x) text"), "Link text");
+        assert_eq!(heading_plain_text("[*Ref*][ref] and [x][]"), "Ref and x");
+        assert_eq!(heading_plain_text("![img](a.png) Title"), " Title");
+        assert_eq!(heading_plain_text("[not a link]"), "[not a link]");
+        assert_eq!(
+            heading_plain_text("<https://example.com>"),
+            "https://example.com"
+        );
+    }
+
+    #[test]
+    fn html_escapes_and_entities() {
+        assert_eq!(heading_plain_text("<code>x</code> tag"), "x tag");
+        assert_eq!(heading_plain_text("a <b> c"), "a  c");
+        assert_eq!(heading_plain_text("1 < 2"), "1 < 2");
+        assert_eq!(heading_plain_text(r"\*not em\*"), "*not em*");
+        assert_eq!(
+            heading_plain_text("Rust &amp; Go &#8212; &#x41;"),
+            "Rust & Go — A"
+        );
+        assert_eq!(heading_plain_text("&unknown;"), "&unknown;");
+    }
+}
@@ -0,0 +1,45 @@
+//! The Markdown processing behind `link-gen`: parse a post into a
+//! [`Document`], look at its blocks and headings, and run transformations
+//! such as adding header links, a table of contents or section numbers over
+//! it.
+
+// Compiles a hard-coded pattern on first use.
+macro_rules! static_regex {
+    ($pattern:expr) => {
+        LazyLock::new(|| Regex::new($pattern).expect("hard-coded regex is valid"))
+    };
+}
+
+mod anchors;
+mod config;
+mod diagnostic;
+mod document;
+mod error;
+mod files;
+mod fragments;
+mod heading;
+mod inline;
+mod link;
+mod number;
+mod site;
+mod slug;
+mod toc;
+
+pub use anchors::{
+    process_markdown_headings, Check, HeadingOptions, HeadingSummary, Pass, ProcessedDocument,
+};
+pub use config::{ChangeKind, CheckConfig, Config, LinkConfig, NumberConfig, TocConfig};
+pub use diagnostic::Diagnostic;
+pub use document::{Block, BlockKind, Document, FrontMatter, HeaderLinksSetting, Heading};
+pub use error::{Error, Severity};
+pub use files::{is_markdown, source_files};
+pub use fragments::{check_links, LinkCheckOptions};
+pub use inline::heading_plain_text;
+pub use link::{LinkPosition, LinkTemplate};
+pub use number::{number_headings, NumberOptions, NumberStyle};
+pub use site::Site;
+pub use slug::{
+    encode_fragment, generate_anchor, generate_gfm_anchor, generate_mdbook_anchor, slugger_by_name,
+    GfmSlugger, KramdownSlugger, MdBookSlugger, Slugger, TemplateSlugger,
+};
+pub use toc::{generate_toc, validate_toc, TocOptions};
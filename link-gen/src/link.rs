@@ -0,0 +1,211 @@
+use crate::error::Error;
+use crate::inline::heading_plain_text;
+use regex::Regex;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Where the header link goes relative to the heading text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkPosition {
+    Before,
+    After,
+    /// The heading text itself becomes the link; `symbol` is not used.
+    Wrap,
+}
+
+impl FromStr for LinkPosition {
+    type Err = String;
+
+    fn from_str(position: &str) -> Result<Self, String> {
+        match position {
+            "before" => Ok(LinkPosition::Before),
+            "after" => Ok(LinkPosition::After),
+            "wrap" => Ok(LinkPosition::Wrap),
+            _ => Err(format!(
+                "unknown link position `{}`; expected before, after or wrap",
+                position
+            )),
+        }
+    }
+}
+
+/// The markup of the link added to each heading.
+#[derive(Debug, Clone)]
+pub struct LinkTemplate {
+    /// Tag name of the link element.
+    pub element: String,
+    /// Link content: text such as 🔗, ¶ or #, or inline HTML like an SVG icon.
+    pub symbol: String,
+    pub class: String,
+    pub position: LinkPosition,
+    /// Accessible name and tooltip, e.g. "Permalink to this section".
+    pub aria_label: Option<String>,
+    pub title: Option<String>,
+}
+
+impl Default for LinkTemplate {
+    fn default() -> Self {
+        LinkTemplate {
+            element: "a".to_string(),
+            symbol: "🔗".to_string(),
+            class: "header-link".to_string(),
+            position: LinkPosition::After,
+            aria_label: None,
+            title: None,
+        }
+    }
+}
+
+impl LinkTemplate {
+    /// The defaults that go with the `.header-link` rules in each theme's
+    /// stylesheet under assets/.
+    pub fn for_theme(theme: &str) -> Option<Self> {
+        let permalink = Some("Permalink to this section".to_string());
+        let template = match theme {
+            "default" => LinkTemplate::default(),
+            "rusty" => LinkTemplate {
+                aria_label: permalink,
+                ..LinkTemplate::default()
+            },
+            "cyberpunk" => LinkTemplate {
+                symbol: "#".to_string(),
+                position: LinkPosition::Before,
+                aria_label: permalink.clone(),
+                title: permalink,
+                ..LinkTemplate::default()
+            },
+            "starwars" => LinkTemplate {
+                symbol: "¶".to_string(),
+                aria_label: permalink.clone(),
+                title: permalink,
+                ..LinkTemplate::default()
+            },
+            _ => return None,
+        };
+        Some(template)
+    }
+
+    /// The heading text combined with the link, as it goes into the line.
+    pub fn render(&self, heading_text: &str, fragment: &str) -> String {
+        let mut attributes = format!(
+            "href=\"#{}\" class=\"{}\"",
+            fragment,
+            escape_attribute(&self.class)
+        );
+        if let Some(label) = &self.aria_label {
+            attributes.push_str(&format!(" aria-label=\"{}\"", escape_attribute(label)));
+        }
+        if let Some(title) = &self.title {
+            attributes.push_str(&format!(" title=\"{}\"", escape_attribute(title)));
+        }
+        let link = |content: &str| format!("<{0} {1}>{2}</{0}>", self.element, attributes, content);
+        match self.position {
+            LinkPosition::Before => format!("{} {}", link(&self.symbol), heading_text),
+            LinkPosition::After => format!("{} {}", heading_text, link(&self.symbol)),
+            LinkPosition::Wrap => link(heading_text),
+        }
+    }
+
+    // The heading's plain text as the renderer will see it once the link is
+    // in place, which is what it derives the id from.
+    pub(crate) fn rendered_text(&self, heading_text: &str) -> String {
+        let symbol = heading_plain_text(&self.symbol);
+        match self.position {
+            LinkPosition::Before => format!("{} {}", symbol, heading_text),
+            LinkPosition::After => format!("{} {}", heading_text, symbol),
+            LinkPosition::Wrap => heading_text.to_string(),
+        }
+    }
+
+    /// Matches a header link generated with this template, capturing its
+    /// content. Fails only for absurdly long element or class names.
+    pub fn link_regex(&self) -> Result<Regex, Error> {
+        let element = regex::escape(&self.element);
+        Regex::new(&format!(
+            r#"<{0}\s[^>]*class="(?:[^"]*\s)?{1}(?:\s[^"]*)?"[^>]*>(.*?)</{0}>"#,
+            element,
+            regex::escape(&self.class)
+        ))
+        .map_err(Error::LinkTemplate)
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+// Returns the heading text without any previously generated header link(s),
+// wherever the link was placed.
+pub(crate) fn strip_header_link<'a>(heading_text: &'a str, link_regex: &Regex) -> &'a str {
+    let mut text = heading_text.trim();
+    while let Some(captures) = link_regex.captures(text) {
+        let (Some(link), Some(content)) = (captures.get(0), captures.get(1)) else {
+            break;
+        };
+        text = if link.end() == text.len() && link.start() == 0 {
+            content.as_str()
+        } else if link.end() == text.len() {
+            &text[..link.start()]
+        } else if link.start() == 0 {
+            &text[link.end()..]
+        } else {
+            break;
+        }
+        .trim();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let mut template = LinkTemplate::default();
+        let link = r##"<a href="#intro" class="header-link">🔗</a>"##;
+        assert_eq!(template.render("Intro", "intro"), format!("Intro {}", link));
+        template.position = LinkPosition::Before;
+        assert_eq!(template.render("Intro", "intro"), format!("{} Intro", link));
+        assert_eq!(template.rendered_text("Intro"), "🔗 Intro");
+        template.position = LinkPosition::Wrap;
+        assert_eq!(
+            template.render("Intro", "intro"),
+            r##"<a href="#intro" class="header-link">Intro</a>"##
+        );
+        assert_eq!(template.rendered_text("Intro"), "Intro");
+    }
+
+    #[test]
+    fn theme_presets() {
+        let cyberpunk = LinkTemplate::for_theme("cyberpunk").unwrap();
+        assert_eq!(
+            cyberpunk.render("Intro", "intro"),
+            r##"<a href="#intro" class="header-link" aria-label="Permalink to this section" title="Permalink to this section">#</a> Intro"##
+        );
+        assert_eq!(LinkTemplate::for_theme("starwars").unwrap().symbol, "¶");
+        assert!(LinkTemplate::for_theme("nope").is_none());
+    }
+
+    #[test]
+    fn links_of_any_position_are_stripped() {
+        for position in [
+            LinkPosition::Before,
+            LinkPosition::After,
+            LinkPosition::Wrap,
+        ] {
+            let template = LinkTemplate {
+                position,
+                title: Some("a \"quoted\" title".to_string()),
+                ..LinkTemplate::default()
+            };
+            let regex = template.link_regex().unwrap();
+            let rendered = template.render("Intro", "intro");
+            assert_eq!(strip_header_link(&rendered, &regex), "Intro");
+        }
+    }
+}
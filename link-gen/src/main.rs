@@ -0,0 +1,1146 @@
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use link_gen::{
+    is_markdown, slugger_by_name, source_files, Check, Config, Diagnostic, Document, Error,
+    HeadingOptions, HeadingSummary, LinkCheckOptions, LinkConfig, LinkPosition, LinkTemplate,
+    NumberOptions, NumberStyle, Pass, Severity, Site, TocOptions,
+};
+use notify::{RecursiveMode, Watcher};
+use rayon::prelude::*;
+use similar::TextDiff;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Post-processing tools for the Markdown posts of a Jekyll site.
+///
+/// Without a command, adds anchor links to headings like `anchors`.
+#[derive(Debug, Parser)]
+#[command(name = "link-gen", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    files: FileArgs,
+
+    /// Read settings from this file instead of looking for linkgen.toml
+    /// or a `linkgen:` section in _config.yml
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "no_config")]
+    config: Option<PathBuf>,
+
+    /// Ignore config files; use only the command line
+    #[arg(long, global = true)]
+    no_config: bool,
+
+    #[command(flatten)]
+    heading: HeadingArgs,
+
+    /// Print more detail; repeat for even more
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// How to print errors and warnings
+    #[arg(long, global = true, value_enum, default_value = "human")]
+    message_format: MessageFormat,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Add or update the anchor links of headings
+    Anchors(FileArgs),
+
+    /// Regenerate the table of contents between <!-- toc --> and
+    /// <!-- tocstop --> markers, or under a "Table of Contents" heading
+    Toc(TocArgs),
+
+    /// Check that a hand-written table of contents matches the headings
+    CheckToc {
+        #[command(flatten)]
+        inputs: InputArgs,
+
+        #[command(flatten)]
+        toc: TocSettings,
+    },
+
+    /// Check that links to #fragments and to other posts of the site point
+    /// at something that exists
+    CheckLinks(InputArgs),
+
+    /// Number sections hierarchically (1., 1.2, 1.2.3), pinning anchors so
+    /// links survive renumbering
+    Number(NumberArgs),
+
+    /// Reprocess posts in place whenever they are saved
+    Watch {
+        /// Directories or files to watch [default: the config's `include`]
+        paths: Vec<PathBuf>,
+
+        /// Wait this long after the last change before processing
+        #[arg(long, value_name = "MS", default_value_t = 300)]
+        debounce: u64,
+    },
+}
+
+// The files a command works on and where its results go.
+#[derive(Debug, Args)]
+struct FileArgs {
+    #[command(flatten)]
+    inputs: InputArgs,
+
+    #[command(flatten)]
+    destination: Destination,
+}
+
+// The files a command works on.
+#[derive(Debug, Args)]
+struct InputArgs {
+    /// Markdown files, directories (searched recursively) or glob patterns;
+    /// with none, or `-`, read standard input
+    inputs: Vec<String>,
+
+    /// Also process _drafts/ in the current directory (the site root)
+    #[arg(long)]
+    drafts: bool,
+
+    /// Also process index.md and Readme.md in the current directory
+    #[arg(long)]
+    pages: bool,
+
+    /// Also process everything the config file's `include` lists
+    #[arg(long)]
+    all: bool,
+}
+
+#[derive(Debug, Args)]
+struct TocArgs {
+    #[command(flatten)]
+    files: FileArgs,
+
+    #[command(flatten)]
+    toc: TocSettings,
+}
+
+// Which headings a table of contents lists.
+#[derive(Debug, Args)]
+struct TocSettings {
+    /// Shallowest heading level listed [default: 2]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=6))]
+    min_depth: Option<u8>,
+
+    /// Deepest heading level listed [default: 3]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=6))]
+    max_depth: Option<u8>,
+
+    /// Leave out the heading with this text or anchor; repeat for more
+    #[arg(long, value_name = "HEADING")]
+    exclude_heading: Vec<String>,
+}
+
+impl TocSettings {
+    fn toc_options(&self, config: &Config, headings: HeadingOptions) -> Result<TocOptions, Error> {
+        let (min_level, max_level) = level_range(
+            config,
+            [
+                ("--min-depth", "toc.min_depth"),
+                ("--max-depth", "toc.max_depth"),
+            ],
+            [self.min_depth, self.max_depth],
+            [config.toc.min_depth, config.toc.max_depth],
+            [2, 3],
+        )?;
+        let mut exclude = config.toc.exclude.clone();
+        exclude.extend(self.exclude_heading.iter().cloned());
+        Ok(TocOptions {
+            headings,
+            min_level,
+            max_level,
+            exclude,
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+struct NumberArgs {
+    #[command(flatten)]
+    files: FileArgs,
+
+    /// Heading level of the top-level sections [default: 2]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=6))]
+    min_depth: Option<u8>,
+
+    /// Deepest heading level numbered [default: 3]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=6))]
+    max_depth: Option<u8>,
+
+    /// Number top-level sections with decimal or roman numerals [default: decimal]
+    #[arg(long)]
+    style: Option<NumberStyle>,
+}
+
+impl NumberArgs {
+    fn number_options(
+        &self,
+        config: &Config,
+        headings: HeadingOptions,
+    ) -> Result<NumberOptions, Error> {
+        let (min_level, max_level) = level_range(
+            config,
+            [
+                ("--min-depth", "number.min_depth"),
+                ("--max-depth", "number.max_depth"),
+            ],
+            [self.min_depth, self.max_depth],
+            [config.number.min_depth, config.number.max_depth],
+            [2, 3],
+        )?;
+        Ok(NumberOptions {
+            headings,
+            min_level,
+            max_level,
+            style: self.style.or(config.number.style).unwrap_or_default(),
+        })
+    }
+}
+
+// How headings are linked; shared by every command. Anything left unset
+// falls back to the config file, then to the built-in default.
+#[derive(Debug, Args)]
+struct HeadingArgs {
+    /// Header id scheme: kramdown (default), gfm, mdbook or template:<pattern>
+    #[arg(long, global = true)]
+    slugger: Option<String>,
+
+    /// Write each generated id onto its heading as an explicit {#id}
+    #[arg(long, global = true, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pin_ids: Option<bool>,
+
+    /// Transliterate headings to ASCII before generating ids
+    #[arg(long, global = true, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    transliterate: Option<bool>,
+
+    /// Start from a theme's link defaults: default, rusty, cyberpunk or starwars
+    #[arg(long, global = true)]
+    theme: Option<String>,
+
+    /// Tag name of the header link element
+    #[arg(long, global = true)]
+    link_element: Option<String>,
+
+    /// Content of the header link, e.g. 🔗, ¶, # or an inline SVG
+    #[arg(long, global = true)]
+    link_symbol: Option<String>,
+
+    /// CSS class of the header link
+    #[arg(long, global = true)]
+    link_class: Option<String>,
+
+    /// Where the link goes relative to the heading text: before, after or wrap
+    #[arg(long, global = true)]
+    link_position: Option<LinkPosition>,
+
+    /// aria-label of the header link
+    #[arg(long, global = true)]
+    aria_label: Option<String>,
+
+    /// title (tooltip) of the header link
+    #[arg(long, global = true)]
+    link_title: Option<String>,
+
+    /// Lowest heading level that gets a link [default: 1]
+    #[arg(long, global = true, value_parser = clap::value_parser!(u8).range(1..=6))]
+    min_level: Option<u8>,
+
+    /// Highest heading level that gets a link [default: 6]
+    #[arg(long, global = true, value_parser = clap::value_parser!(u8).range(1..=6))]
+    max_level: Option<u8>,
+}
+
+// Where the processed documents go; standard output unless told otherwise.
+#[derive(Debug, Args)]
+#[group(multiple = false)]
+struct Destination {
+    /// Overwrite the input files
+    #[arg(short, long)]
+    in_place: bool,
+
+    /// Write to this file, or into this directory when there are several inputs
+    #[arg(short, long, value_name = "PATH|DIR")]
+    output: Option<PathBuf>,
+
+    /// Write the processed documents to standard output
+    #[arg(long)]
+    stdout: bool,
+
+    /// Write nothing; print a diff and fail if any file is out of date
+    #[arg(long)]
+    check: bool,
+}
+
+impl HeadingArgs {
+    // Command-line flags first, then the config file, then the defaults.
+    fn heading_options(&self, config: &Config) -> Result<HeadingOptions, Error> {
+        let slugger_name = self
+            .slugger
+            .as_deref()
+            .or(config.slugger.as_deref())
+            .unwrap_or("kramdown");
+        let slugger = slugger_by_name(slugger_name)
+            .ok_or_else(|| Error::Usage(format!("unknown slugger `{}`", slugger_name)))?;
+        let theme = self
+            .theme
+            .as_deref()
+            .or(config.theme.as_deref())
+            .unwrap_or("default");
+        let mut link = LinkTemplate::for_theme(theme)
+            .ok_or_else(|| Error::Usage(format!("unknown theme `{}`", theme)))?;
+        for overrides in [&config.link, &self.link_overrides()] {
+            if let Some(element) = &overrides.element {
+                link.element = element.clone();
+            }
+            if let Some(symbol) = &overrides.symbol {
+                link.symbol = symbol.clone();
+            }
+            if let Some(class) = &overrides.class {
+                link.class = class.clone();
+            }
+            if let Some(position) = overrides.position {
+                link.position = position;
+            }
+            if let Some(label) = &overrides.aria_label {
+                link.aria_label = Some(label.clone());
+            }
+            if let Some(title) = &overrides.title {
+                link.title = Some(title.clone());
+            }
+        }
+        let (min_level, max_level) = level_range(
+            config,
+            [("--min-level", "min_level"), ("--max-level", "max_level")],
+            [self.min_level, self.max_level],
+            [config.min_level, config.max_level],
+            [1, 6],
+        )?;
+
+        Ok(HeadingOptions {
+            slugger,
+            pin_ids: self.pin_ids.or(config.pin_ids).unwrap_or(false),
+            transliterate: self.transliterate.or(config.transliterate).unwrap_or(false),
+            link,
+            min_level,
+            max_level,
+        })
+    }
+
+    fn link_overrides(&self) -> LinkConfig {
+        LinkConfig {
+            element: self.link_element.clone(),
+            symbol: self.link_symbol.clone(),
+            class: self.link_class.clone(),
+            position: self.link_position,
+            aria_label: self.aria_label.clone(),
+            title: self.link_title.clone(),
+        }
+    }
+}
+
+// A range of heading levels, resolved like every other setting: the flags,
+// then the config file, then the defaults. `names` are the flag and config
+// key of each end. Both check that each level is from 1 to 6; an
+// inverted range is blamed on wherever its ends came from.
+fn level_range(
+    config: &Config,
+    names: [(&str, &str); 2],
+    flags: [Option<u8>; 2],
+    configured: [Option<u8>; 2],
+    defaults: [u8; 2],
+) -> Result<(usize, usize), Error> {
+    let value = |end: usize| flags[end].or(configured[end]).unwrap_or(defaults[end]);
+    let (min, max) = (value(0), value(1));
+    if min <= max {
+        return Ok((min as usize, max as usize));
+    }
+    let describe = |end: usize| {
+        let (flag, key) = names[end];
+        match (flags[end], configured[end], &config.path) {
+            (Some(level), _, _) => format!("{} {}", flag, level),
+            (None, Some(level), Some(path)) => {
+                format!("`{} = {}` in {}", key, level, path.display())
+            }
+            (None, Some(level), None) => format!("`{} = {}`", key, level),
+            (None, None, _) => format!("the default {} of {}", flag, defaults[end]),
+        }
+    };
+    let message = format!("{} is greater than {}", describe(0), describe(1));
+    match &config.path {
+        // Only the config file is to blame when no flag was given.
+        Some(path) if flags.iter().all(Option::is_none) => Err(Error::Config {
+            path: path.clone(),
+            position: None,
+            message,
+        }),
+        _ => Err(Error::Usage(message)),
+    }
+}
+
+impl Cli {
+    // The config file in effect and the heading options it and the
+    // command line add up to.
+    fn settings(&self) -> Result<(Config, HeadingOptions), Error> {
+        // The implicit `anchors` command's files and destination would be
+        // ignored by any other command.
+        if self.command.is_some() && self.files.is_set() {
+            let message = "input and output options go after the command";
+            return Err(Error::Usage(message.to_string()));
+        }
+        let config = if self.no_config {
+            Config::default()
+        } else {
+            Config::load(self.config.as_deref())?
+        };
+        let options = self.heading.heading_options(&config)?;
+        Ok((config, options))
+    }
+
+    fn reporter(&self) -> Reporter {
+        Reporter {
+            // -1 for --quiet, 0 by default, 1 and up for each -v.
+            verbosity: if self.quiet { -1 } else { self.verbose as i8 },
+            format: self.message_format,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MessageFormat {
+    Human,
+    // One JSON object per line on standard error, for editors.
+    Json,
+}
+
+// Everything that goes to standard error. In JSON mode only diagnostics are
+// printed, so the output stays machine-readable.
+struct Reporter {
+    verbosity: i8,
+    format: MessageFormat,
+}
+
+impl Reporter {
+    // Errors are always shown, warnings unless --quiet.
+    fn diagnostic(&self, diagnostic: &Diagnostic) {
+        if diagnostic.severity == Severity::Warning && self.verbosity < 0 {
+            return;
+        }
+        match self.format {
+            MessageFormat::Human => eprintln!("{}", diagnostic),
+            MessageFormat::Json => match serde_json::to_string(diagnostic) {
+                Ok(json) => eprintln!("{}", json),
+                Err(err) => eprintln!("error: {}", err),
+            },
+        }
+    }
+
+    // An error outside any document, or one without its source at hand.
+    fn error(&self, error: &Error, file: Option<&Path>) {
+        self.diagnostic(&Diagnostic::new(error, file, None));
+    }
+
+    // A progress message, shown from the given verbosity on.
+    fn note(&self, verbosity: i8, message: fmt::Arguments) {
+        if self.format == MessageFormat::Human && self.verbosity >= verbosity {
+            eprintln!("{}", message);
+        }
+    }
+
+    fn summary(&self, file: &str, summary: &HeadingSummary) {
+        let verbosity = if summary.changed() { 0 } else { 1 };
+        self.note(verbosity, format_args!("{}: {}", file, summary));
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let reporter = cli.reporter();
+
+    let (config, options) = match cli.settings() {
+        Ok(settings) => settings,
+        Err(err) => {
+            reporter.error(&err, None);
+            return ExitCode::from(2);
+        }
+    };
+    match &cli.command {
+        Some(Command::Anchors(files)) => run_files(files, &config, &options, &reporter),
+        Some(Command::Toc(args)) => match args.toc.toc_options(&config, options) {
+            Ok(toc) => run_files(&args.files, &config, &toc, &reporter),
+            Err(err) => {
+                reporter.error(&err, None);
+                ExitCode::from(2)
+            }
+        },
+        Some(Command::Number(args)) => match args.number_options(&config, options) {
+            Ok(number) => run_files(&args.files, &config, &number, &reporter),
+            Err(err) => {
+                reporter.error(&err, None);
+                ExitCode::from(2)
+            }
+        },
+        Some(Command::CheckLinks(inputs)) => {
+            // Posts link to each other by URL, so the whole site is indexed
+            // first.
+            let site = match Site::load(&config.root, &options) {
+                Ok(site) => site,
+                Err(err) => {
+                    reporter.error(&err, None);
+                    return ExitCode::from(2);
+                }
+            };
+            let links = LinkCheckOptions {
+                headings: options,
+                site,
+            };
+            run_checks(inputs, &config, &links, &reporter)
+        }
+        Some(Command::CheckToc { inputs, toc }) => match toc.toc_options(&config, options) {
+            Ok(toc) => run_checks(inputs, &config, &toc, &reporter),
+            Err(err) => {
+                reporter.error(&err, None);
+                ExitCode::from(2)
+            }
+        },
+        Some(Command::Watch { paths, debounce }) => {
+            let paths = if paths.is_empty() {
+                config.include_paths()
+            } else {
+                paths.clone()
+            };
+            let debounce = Duration::from_millis(*debounce);
+            run_watch(&paths, debounce, &config, &options, &reporter)
+        }
+        None => run_files(&cli.files, &config, &options, &reporter),
+    }
+}
+
+impl FileArgs {
+    fn is_set(&self) -> bool {
+        let inputs = &self.inputs;
+        let destination = &self.destination;
+        !inputs.inputs.is_empty()
+            || inputs.drafts
+            || inputs.pages
+            || inputs.all
+            || destination.in_place
+            || destination.output.is_some()
+            || destination.stdout
+            || destination.check
+    }
+}
+
+impl InputArgs {
+    // The inputs named on the command line plus those the flags add.
+    fn input_args(&self, config: &Config) -> Vec<String> {
+        let mut input_args = self.inputs.clone();
+        if self.all {
+            for path in config.include_paths() {
+                input_args.push(path.to_string_lossy().into_owned());
+            }
+        }
+        if self.drafts && Path::new("_drafts").is_dir() {
+            input_args.push("_drafts".to_string());
+        }
+        if self.pages {
+            for page in ["index.md", "Readme.md"] {
+                if Path::new(page).is_file() {
+                    input_args.push(page.to_string());
+                }
+            }
+        }
+        input_args
+    }
+}
+
+// Runs `pass` over the files `args` names, or over standard input.
+fn run_files(args: &FileArgs, config: &Config, pass: &dyn Pass, reporter: &Reporter) -> ExitCode {
+    let input_args = args.inputs.input_args(config);
+    if input_args.is_empty() || input_args == ["-"] {
+        return run_filter(&args.destination, config, pass, reporter);
+    }
+    let inputs = match collect_inputs(&input_args, config) {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            reporter.error(&err.into(), None);
+            return ExitCode::from(2);
+        }
+    };
+
+    // With several inputs (or a directory) `--output` names a directory.
+    let output_is_dir = args.destination.output.as_deref().is_some_and(|output| {
+        output.is_dir() || inputs.len() > 1 || input_args.iter().any(|i| Path::new(i).is_dir())
+    });
+
+    let results: Vec<_> = inputs
+        .par_iter()
+        .map(|input| {
+            reporter.note(2, format_args!("Processing {}", input.path.display()));
+            process_input(input, &args.destination, pass, output_is_dir)
+        })
+        .collect();
+
+    let mut failed = false;
+    let mut out_of_date = 0;
+    let mut total = HeadingSummary::default();
+    let mut stdout = io::stdout().lock();
+    // Whether the last file written to standard output lacks a final newline.
+    let mut unterminated = false;
+    for (input, result) in inputs.iter().zip(results) {
+        match result {
+            Ok(outcome) => {
+                for warning in &outcome.warnings {
+                    reporter.diagnostic(warning);
+                }
+                total += outcome.summary;
+                reporter.summary(&input.path.display().to_string(), &outcome.summary);
+                if let Some(path) = outcome.written_to {
+                    reporter.note(1, format_args!("  written to {}", path.display()));
+                }
+                // Changes the config doesn't check for don't fail the run.
+                let diff = outcome
+                    .diff
+                    .filter(|_| config.check.fails(&outcome.summary));
+                if diff.is_some() {
+                    out_of_date += 1;
+                }
+                let printed = match (outcome.stdout, diff) {
+                    // Keep this file from starting on the last line of one
+                    // that doesn't end in a newline; a file on its own is
+                    // written exactly.
+                    (Some(content), _) => {
+                        let separator: &[u8] = if unterminated { b"\n" } else { b"" };
+                        unterminated = !content.is_empty() && !content.ends_with('\n');
+                        stdout
+                            .write_all(separator)
+                            .and_then(|_| stdout.write_all(content.as_bytes()))
+                    }
+                    (None, Some(diff)) => stdout.write_all(diff.as_bytes()),
+                    (None, None) => Ok(()),
+                };
+                if let Err(err) = printed {
+                    reporter.error(&err.into(), None);
+                    return ExitCode::FAILURE;
+                }
+            }
+            Err(diagnostic) => {
+                reporter.diagnostic(&diagnostic);
+                failed = true;
+            }
+        }
+    }
+    if inputs.len() > 1 {
+        reporter.note(0, format_args!("{} files: {}", inputs.len(), total));
+    }
+    if args.destination.check && out_of_date > 0 {
+        reporter.note(
+            -1,
+            format_args!(
+                "{} file(s) out of date; run link-gen --in-place to update them",
+                out_of_date
+            ),
+        );
+    }
+
+    if failed || out_of_date > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// Runs `check` over the files `args` names, or over standard input, and
+// reports what it finds. Fails if there is anything worse than a warning.
+fn run_checks(
+    args: &InputArgs,
+    config: &Config,
+    check: &dyn Check,
+    reporter: &Reporter,
+) -> ExitCode {
+    let input_args = args.input_args(config);
+    let inputs = if input_args.is_empty() || input_args == ["-"] {
+        None
+    } else {
+        match collect_inputs(&input_args, config) {
+            Ok(inputs) => Some(inputs),
+            Err(err) => {
+                reporter.error(&err.into(), None);
+                return ExitCode::from(2);
+            }
+        }
+    };
+
+    let results: Vec<Vec<Diagnostic>> = match &inputs {
+        None => {
+            let mut content = String::new();
+            if let Err(err) = io::stdin().read_to_string(&mut content) {
+                reporter.error(&err.into(), None);
+                return ExitCode::FAILURE;
+            }
+            vec![check_document(check, &content, None)]
+        }
+        Some(inputs) => inputs
+            .par_iter()
+            .map(|input| {
+                reporter.note(2, format_args!("Checking {}", input.path.display()));
+                match fs::read_to_string(&input.path) {
+                    Ok(content) => check_document(check, &content, Some(&input.path)),
+                    Err(err) => vec![*io_failure(err, &input.path)],
+                }
+            })
+            .collect(),
+    };
+
+    let mut problems = 0;
+    let mut failed_files = 0;
+    for diagnostics in &results {
+        let mut failed = false;
+        for diagnostic in diagnostics {
+            reporter.diagnostic(diagnostic);
+            if diagnostic.severity == Severity::Error {
+                problems += 1;
+                failed = true;
+            }
+        }
+        failed_files += failed as usize;
+    }
+    if results.len() > 1 || problems > 0 {
+        reporter.note(
+            0,
+            format_args!(
+                "{} file(s) checked: {} problem(s) in {} file(s)",
+                results.len(),
+                problems,
+                failed_files
+            ),
+        );
+    }
+
+    if problems > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// The document's warnings and what `check` finds in it, in that order.
+fn check_document(check: &dyn Check, content: &str, path: Option<&Path>) -> Vec<Diagnostic> {
+    let document = Document::parse(content);
+    let mut diagnostics: Vec<Diagnostic> = document
+        .warnings()
+        .iter()
+        .map(|warning| Diagnostic::new(warning, path, Some(content)))
+        .collect();
+    match check.check(&document) {
+        Ok(problems) => diagnostics.extend(
+            problems
+                .iter()
+                .map(|problem| Diagnostic::new(problem, path, Some(content))),
+        ),
+        Err(err) => diagnostics.push(Diagnostic::new(&err, path, Some(content))),
+    }
+    diagnostics
+}
+
+// Filter mode for editors and pipelines: the document comes in on standard
+// input and only the processed document goes to standard output, so
+// everything else, including the summary, goes to standard error.
+fn run_filter(
+    destination: &Destination,
+    config: &Config,
+    pass: &dyn Pass,
+    reporter: &Reporter,
+) -> ExitCode {
+    if destination.in_place {
+        reporter.error(
+            &Error::Usage("--in-place needs input files".to_string()),
+            None,
+        );
+        return ExitCode::from(2);
+    }
+
+    let mut content = String::new();
+    if let Err(err) = io::stdin().read_to_string(&mut content) {
+        reporter.error(&err.into(), None);
+        return ExitCode::FAILURE;
+    }
+    // Nothing is written on failure, so a failed editor filter can be undone
+    // instead of replacing the buffer with half a document.
+    let document = Document::parse(&content);
+    for warning in document.warnings() {
+        reporter.diagnostic(&Diagnostic::new(warning, None, Some(&content)));
+    }
+    let processed = match pass.apply(&document) {
+        Ok(processed) => processed,
+        Err(err) => {
+            reporter.diagnostic(&Diagnostic::new(&err, None, Some(&content)));
+            return ExitCode::FAILURE;
+        }
+    };
+    reporter.note(1, format_args!("<stdin>: {}", processed.summary));
+
+    let written = if destination.check {
+        if processed.content == content || !config.check.fails(&processed.summary) {
+            return ExitCode::SUCCESS;
+        }
+        let diff = TextDiff::from_lines(&content, &processed.content)
+            .unified_diff()
+            .header("a/<stdin>", "b/<stdin>")
+            .to_string();
+        io::stdout().write_all(diff.as_bytes())
+    } else if let Some(output) = &destination.output {
+        fs::write(output, &processed.content)
+    } else {
+        let mut stdout = io::stdout().lock();
+        stdout
+            .write_all(processed.content.as_bytes())
+            .and_then(|_| stdout.flush())
+    };
+    match written {
+        Ok(()) if destination.check => ExitCode::FAILURE,
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            reporter.error(&err.into(), destination.output.as_deref());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Watch mode: reprocess Markdown files in place as they are saved.
+// Editors often write a file several times per save, so changes are
+// collected until things have been quiet for `debounce`. Files we just
+// wrote ourselves are recognised by their content and skipped, so our own
+// writes don't trigger another round.
+fn run_watch(
+    paths: &[PathBuf],
+    debounce: Duration,
+    config: &Config,
+    pass: &dyn Pass,
+    reporter: &Reporter,
+) -> ExitCode {
+    let (sender, events) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            reporter.error(&Error::Watch(err), None);
+            return ExitCode::FAILURE;
+        }
+    };
+    for path in paths {
+        if let Err(err) = watcher.watch(path, RecursiveMode::Recursive) {
+            reporter.error(&Error::Watch(err), Some(path));
+            return ExitCode::from(2);
+        }
+    }
+    reporter.note(
+        0,
+        format_args!("Watching for changes, press Ctrl-C to stop"),
+    );
+
+    let mut last_written: HashMap<PathBuf, String> = HashMap::new();
+    while let Ok(first) = events.recv() {
+        let mut changed = BTreeSet::new();
+        let mut collect = |event: notify::Result<notify::Event>| match event {
+            Ok(event) if event.kind.is_create() || event.kind.is_modify() => {
+                changed.extend(event.paths);
+            }
+            Ok(_) => {}
+            Err(err) => reporter.error(&Error::Watch(err), None),
+        };
+        collect(first);
+        while let Ok(event) = events.recv_timeout(debounce) {
+            collect(event);
+        }
+
+        for path in changed {
+            if !path.is_file() || !is_markdown(&path) || config.excludes(&path) {
+                continue;
+            }
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) => {
+                    reporter.error(&err.into(), Some(&path));
+                    continue;
+                }
+            };
+            if last_written.get(&path) == Some(&content) {
+                continue;
+            }
+            let document = Document::parse(&content);
+            for warning in document.warnings() {
+                reporter.diagnostic(&Diagnostic::new(warning, Some(&path), Some(&content)));
+            }
+            let processed = match pass.apply(&document) {
+                Ok(processed) => processed,
+                Err(err) => {
+                    reporter.diagnostic(&Diagnostic::new(&err, Some(&path), Some(&content)));
+                    continue;
+                }
+            };
+            if processed.content != content {
+                if let Err(err) = fs::write(&path, &processed.content) {
+                    reporter.error(&err.into(), Some(&path));
+                    continue;
+                }
+                last_written.insert(path.clone(), processed.content);
+            }
+            reporter.summary(&path.display().to_string(), &processed.summary);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+// What processing one input produced.
+struct FileOutcome {
+    summary: HeadingSummary,
+    // Problems that did not stop the file from being processed.
+    warnings: Vec<Diagnostic>,
+    // Where the result was written, if anywhere.
+    written_to: Option<PathBuf>,
+    // The result, when it goes to standard output.
+    stdout: Option<String>,
+    // In --check mode, the pending changes as a unified diff.
+    diff: Option<String>,
+}
+
+fn process_input(
+    input: &InputFile,
+    destination: &Destination,
+    pass: &dyn Pass,
+    output_is_dir: bool,
+) -> Result<FileOutcome, Box<Diagnostic>> {
+    let path = input.path.as_path();
+    let content = fs::read_to_string(path).map_err(|err| io_failure(err, path))?;
+    let document = Document::parse(&content);
+    let processed = pass
+        .apply(&document)
+        .map_err(|err| Box::new(Diagnostic::new(&err, Some(path), Some(&content))))?;
+    let mut outcome = FileOutcome {
+        summary: processed.summary,
+        warnings: document
+            .warnings()
+            .iter()
+            .map(|warning| Diagnostic::new(warning, Some(path), Some(&content)))
+            .collect(),
+        written_to: None,
+        stdout: None,
+        diff: None,
+    };
+    let write = |target: &Path, processed: &str| {
+        fs::write(target, processed).map_err(|err| io_failure(err, target))
+    };
+
+    if destination.check {
+        if processed.content != content {
+            let path = path.display().to_string();
+            let diff = TextDiff::from_lines(&content, &processed.content)
+                .unified_diff()
+                .header(&format!("a/{}", path), &format!("b/{}", path))
+                .to_string();
+            outcome.diff = Some(diff);
+        }
+    } else if destination.in_place {
+        // Leave untouched files alone so their timestamps don't change.
+        if processed.content != content {
+            write(path, &processed.content)?;
+            outcome.written_to = Some(path.to_path_buf());
+        }
+    } else if let Some(output) = &destination.output {
+        let output_path = if output_is_dir {
+            output.join(&input.relative)
+        } else {
+            output.clone()
+        };
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent).map_err(|err| io_failure(err, parent))?;
+        }
+        write(&output_path, &processed.content)?;
+        outcome.written_to = Some(output_path);
+    } else {
+        outcome.stdout = Some(processed.content);
+    }
+    Ok(outcome)
+}
+
+fn io_failure(err: io::Error, file: &Path) -> Box<Diagnostic> {
+    Box::new(Diagnostic::new(&err.into(), Some(file), None))
+}
+
+// A Markdown file to process, and its path relative to the directory it was
+// found in (or just its file name), used to lay out `--output` directories.
+#[derive(Debug)]
+struct InputFile {
+    path: PathBuf,
+    relative: PathBuf,
+}
+
+// Expands the command-line inputs into the Markdown files they name,
+// leaving out what the config excludes unless it was named directly. A file
+// named more than once is processed once, so two threads never write it.
+fn collect_inputs(inputs: &[String], config: &Config) -> io::Result<Vec<InputFile>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            for file in source_files(path)? {
+                if !is_markdown(&file) || config.excludes(&file) {
+                    continue;
+                }
+                let relative = file.strip_prefix(path).unwrap_or(&file).to_path_buf();
+                files.push(InputFile {
+                    path: file,
+                    relative,
+                });
+            }
+        } else if !path.exists() && input.contains(['*', '?', '[']) {
+            let paths = glob::glob(input)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            let mut matched = false;
+            for path in paths {
+                let path = path.map_err(io::Error::from)?;
+                if path.is_file() && !config.excludes(&path) {
+                    files.push(InputFile::from_file(path));
+                    matched = true;
+                }
+            }
+            if !matched {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("`{}` matched no files", input),
+                ));
+            }
+        } else if path.is_file() {
+            files.push(InputFile::from_file(path.to_path_buf()));
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("`{}` does not exist", input),
+            ));
+        }
+    }
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(fs::canonicalize(&file.path).unwrap_or(file.path.clone())));
+    Ok(files)
+}
+
+impl InputFile {
+    fn from_file(path: PathBuf) -> Self {
+        let relative = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+        InputFile { path, relative }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_flags_before_the_command() {
+        let cli = Cli::try_parse_from(["link-gen", "-q", "toc", "post.md"]).unwrap();
+        assert!(cli.quiet);
+        let Some(Command::Toc(args)) = &cli.command else {
+            panic!("expected the toc command, got {:?}", cli.command);
+        };
+        assert_eq!(args.files.inputs.inputs, ["post.md"]);
+        assert!(!cli.files.is_set());
+    }
+
+    #[test]
+    fn global_flags_after_the_command() {
+        let cli = Cli::try_parse_from(["link-gen", "check-links", "--slugger", "gfm", "post.md"])
+            .unwrap();
+        assert_eq!(cli.heading.slugger.as_deref(), Some("gfm"));
+        assert!(matches!(cli.command, Some(Command::CheckLinks(_))));
+    }
+
+    #[test]
+    fn files_without_a_command() {
+        let cli = Cli::try_parse_from(["link-gen", "--no-config", "post.md", "--check"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.files.inputs.inputs, ["post.md"]);
+        assert!(cli.files.destination.check);
+    }
+
+    fn heading_options(args: &[&str]) -> HeadingOptions {
+        let cli = Cli::try_parse_from(args).unwrap();
+        cli.heading.heading_options(&Config::default()).unwrap()
+    }
+
+    #[test]
+    fn link_markup_settings() {
+        let options = heading_options(&["link-gen", "--theme", "cyberpunk", "post.md"]);
+        assert_eq!(options.link.position, LinkPosition::Before);
+        assert_eq!(options.link.symbol, "#");
+        let options = heading_options(&[
+            "link-gen",
+            "--theme",
+            "cyberpunk",
+            "--link-position",
+            "wrap",
+            "--link-class",
+            "anchor",
+        ]);
+        assert_eq!(options.link.position, LinkPosition::Wrap);
+        assert_eq!(options.link.class, "anchor");
+        assert_eq!(options.link.symbol, "#");
+    }
+
+    #[test]
+    fn slugger_and_level_settings() {
+        let options = heading_options(&["link-gen", "--slugger", "gfm", "--min-level", "2"]);
+        assert_eq!(options.slugger.slug("Café au lait", 2), "café-au-lait");
+        assert_eq!((options.min_level, options.max_level), (2, 6));
+        let cli = Cli::try_parse_from(["link-gen", "--slugger", "nope"]).unwrap();
+        assert!(cli.heading.heading_options(&Config::default()).is_err());
+    }
+
+    #[test]
+    fn level_ranges() {
+        let mut config = Config::default();
+        config.path = Some(PathBuf::from("linkgen.toml"));
+        let names = [
+            ("--min-depth", "toc.min_depth"),
+            ("--max-depth", "toc.max_depth"),
+        ];
+        let range = |flags, configured| level_range(&config, names, flags, configured, [2, 3]);
+        assert_eq!(range([None, None], [None, None]).unwrap(), (2, 3));
+        assert_eq!(range([None, Some(4)], [Some(1), Some(6)]).unwrap(), (1, 4));
+
+        let Err(Error::Config { path, message, .. }) = range([None, None], [Some(5), None]) else {
+            panic!("a bad config file is not a usage error");
+        };
+        assert_eq!(path, Path::new("linkgen.toml"));
+        assert_eq!(
+            message,
+            "`toc.min_depth = 5` in linkgen.toml is greater than the default --max-depth of 3"
+        );
+
+        let Err(Error::Usage(message)) = range([Some(4), None], [None, Some(3)]) else {
+            panic!("flags are to blame");
+        };
+        assert_eq!(
+            message,
+            "--min-depth 4 is greater than `toc.max_depth = 3` in linkgen.toml"
+        );
+    }
+
+    #[test]
+    fn file_options_before_a_command_are_rejected() {
+        let cli = Cli::try_parse_from(["link-gen", "--in-place", "toc", "post.md"]).unwrap();
+        assert!(matches!(cli.settings(), Err(Error::Usage(_))));
+    }
+}
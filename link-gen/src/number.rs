@@ -0,0 +1,339 @@
+use crate::anchors::{scan_headings, HeadingOptions, HeadingSummary, Pass, ProcessedDocument};
+use crate::document::Document;
+use crate::error::Error;
+use crate::heading::{has_class, pin_attribute_id};
+use crate::toc::is_toc_heading;
+use regex::Regex;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// How top-level sections are numbered; deeper ones always use digits, as
+/// in `II.3.1`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberStyle {
+    #[default]
+    Decimal,
+    Roman,
+}
+
+impl FromStr for NumberStyle {
+    type Err = String;
+
+    fn from_str(style: &str) -> Result<Self, String> {
+        match style {
+            "decimal" => Ok(NumberStyle::Decimal),
+            "roman" => Ok(NumberStyle::Roman),
+            _ => Err(format!(
+                "unknown numbering style `{}`; expected decimal or roman",
+                style
+            )),
+        }
+    }
+}
+
+/// Settings for one run of `number_headings`.
+pub struct NumberOptions {
+    /// How anchors are generated, as for the header links.
+    pub headings: HeadingOptions,
+    /// Headings from `min_level` to `max_level` are numbered; `min_level`
+    /// headings are the top-level sections.
+    pub min_level: usize,
+    pub max_level: usize,
+    pub style: NumberStyle,
+}
+
+impl Default for NumberOptions {
+    fn default() -> Self {
+        NumberOptions {
+            headings: HeadingOptions::default(),
+            min_level: 2,
+            max_level: 3,
+            style: NumberStyle::default(),
+        }
+    }
+}
+
+// Numbers the headings.
+impl Pass for NumberOptions {
+    fn apply(&self, document: &Document) -> Result<ProcessedDocument, Error> {
+        number_headings(document, self)
+    }
+}
+
+// A section number at the start of a heading, as `number_headings` writes
+// it. Only marked numbers are replaced, so headings that start with a
+// number of their own, like "2.0 Changes", keep it.
+static SECTION_NUMBER_REGEX: LazyLock<Regex> =
+    static_regex!(r#"^<span class="section-number">[^<]*</span>[ \t]*"#);
+
+/// Numbers headings hierarchically (`1.`, `1.2`, `1.2.3`), replacing the
+/// numbers of an earlier run so sections can be inserted or moved freely.
+/// Headings with an `{: .unnumbered}` class, the table of contents heading
+/// and headings outside the levels being numbered are skipped, and lose any
+/// number they had.
+///
+/// Numbers are written as `<span class="section-number">1.2</span>`, which
+/// is how later runs recognise them and what themes can style.
+///
+/// Anchors are derived from the text without the number and pinned onto
+/// each numbered heading as an explicit `{#id}`, so links to a section keep
+/// working when its number changes.
+pub fn number_headings(
+    document: &Document,
+    options: &NumberOptions,
+) -> Result<ProcessedDocument, Error> {
+    let lines = &document.lines;
+    let headings = scan_headings(document, &options.headings)?;
+    let in_range = |level: usize| (options.min_level..=options.max_level).contains(&level);
+
+    // The first line of each heading's text, and the text on it.
+    let first_lines: Vec<(usize, &str)> = headings
+        .iter()
+        .map(|scanned| match scanned.line.leading_lines.first() {
+            Some(&first) => (scanned.heading.lines.start, first),
+            None => (scanned.line.line, scanned.line.text),
+        })
+        .collect();
+
+    // Anchors and text come from the headings as they would be without
+    // numbers.
+    let mut unnumbered: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    for &(index, text) in &first_lines {
+        if let Some(number) = SECTION_NUMBER_REGEX.find(text) {
+            let start = offset_in(lines[index], text);
+            unnumbered[index].replace_range(start..start + number.end(), "");
+        }
+    }
+    let unnumbered_content = document.reassemble(&unnumbered);
+    let unnumbered_document = Document::parse(&unnumbered_content);
+    let unnumbered_headings = unnumbered_document.headings(&options.headings)?;
+
+    let mut output: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    let mut summary = HeadingSummary::default();
+    // Levels of the sections the next heading can be a subsection of, and
+    // the number of each.
+    let mut parents: Vec<usize> = Vec::new();
+    let mut counters: Vec<usize> = Vec::new();
+    let numbered = headings.iter().zip(&first_lines).zip(&unnumbered_headings);
+    for ((scanned, &(index, text)), unnumbered) in numbered {
+        let heading = &scanned.heading;
+        let anchor = &unnumbered.anchor;
+        let old_number = SECTION_NUMBER_REGEX.find(text);
+        let start = offset_in(lines[index], text);
+        let skipped = !in_range(heading.level)
+            || is_toc_heading(&unnumbered.text)
+            || scanned
+                .attributes
+                .is_some_and(|attributes| has_class(attributes, "unnumbered"));
+        if skipped {
+            if let Some(number) = old_number {
+                output[index].replace_range(start..start + number.end(), "");
+                summary.removed += 1;
+            }
+            continue;
+        }
+
+        while parents.last().is_some_and(|&level| level >= heading.level) {
+            parents.pop();
+        }
+        parents.push(heading.level);
+        counters.truncate(parents.len());
+        counters.resize(parents.len(), 0);
+        counters[parents.len() - 1] += 1;
+
+        // The heading and a possible attribute list on the line after it.
+        let touched = heading.lines.start..(heading.lines.end + 1).min(output.len());
+        let before = output[touched.clone()].to_vec();
+
+        // Pin the anchor first: it is at the end of the heading, so the
+        // position of the text stays valid for the number.
+        if !scanned.explicit_id {
+            let line = scanned.line.line;
+            match (scanned.line.attributes, scanned.attributes) {
+                (Some(attributes), _) => {
+                    if let Some(at) = output[line].rfind(attributes) {
+                        let pinned = pin_attribute_id(attributes, anchor);
+                        output[line].replace_range(at..at + attributes.len(), &pinned);
+                    }
+                }
+                // kramdown's attribute list on the line after the heading.
+                (None, Some(attributes)) => {
+                    let next = heading.lines.end;
+                    output[next] =
+                        output[next].replacen(attributes, &pin_attribute_id(attributes, anchor), 1);
+                }
+                (None, None) => {
+                    let trimmed = output[line].trim_end().len();
+                    output[line].truncate(trimmed);
+                    output[line].push_str(&format!(" {{#{}}}", anchor));
+                }
+            }
+        }
+        let number = section_number(&counters, options.style);
+        let old_end = start + old_number.map_or(0, |number| number.end());
+        output[index].replace_range(
+            start..old_end,
+            &format!(r#"<span class="section-number">{}</span> "#, number),
+        );
+
+        if output[touched] == before[..] {
+            summary.unchanged += 1;
+        } else if old_number.is_some() {
+            summary.updated += 1;
+        } else {
+            summary.added += 1;
+        }
+    }
+
+    Ok(ProcessedDocument {
+        content: document.reassemble(&output),
+        summary,
+    })
+}
+
+// `1.` for a top-level section, `1.2` and `1.2.3` below it.
+fn section_number(counters: &[usize], style: NumberStyle) -> String {
+    let mut parts: Vec<String> = counters.iter().map(|n| n.to_string()).collect();
+    if style == NumberStyle::Roman {
+        parts[0] = roman_numeral(counters[0]);
+    }
+    let number = parts.join(".");
+    if counters.len() == 1 {
+        format!("{}.", number)
+    } else {
+        number
+    }
+}
+
+fn roman_numeral(mut n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut numeral = String::new();
+    for (value, letters) in NUMERALS {
+        while n >= value {
+            numeral.push_str(letters);
+            n -= value;
+        }
+    }
+    numeral
+}
+
+// Byte offset of `part`, which is a slice of `line`, within it.
+fn offset_in(line: &str, part: &str) -> usize {
+    part.as_ptr() as usize - line.as_ptr() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(content: &str, options: &NumberOptions) -> ProcessedDocument {
+        number_headings(&Document::parse(content), options).unwrap()
+    }
+
+    fn numbered(number: &str) -> String {
+        format!(r#"<span class="section-number">{}</span>"#, number)
+    }
+
+    #[test]
+    fn headings_starting_with_numbers_keep_them() {
+        let content = "## 2.0 Changes\n\n## 3.14 is pi\n\n### C. Notes\n";
+        let processed = number(content, &NumberOptions::default());
+        assert_eq!(
+            processed.content,
+            format!(
+                "## {} 2.0 Changes {{#changes-}}\n\n## {} 3.14 is pi {{#is-pi-}}\n\n### {} C. Notes {{#c-notes-}}\n",
+                numbered("1."),
+                numbered("2."),
+                numbered("2.1"),
+            )
+        );
+        assert_eq!(processed.summary.added, 3);
+        assert_eq!(processed.summary.updated, 0);
+    }
+
+    #[test]
+    fn a_second_run_changes_nothing() {
+        let content = "## One\n\n### Sub\n\nTwo\n---\n\n## Table of Contents\n";
+        for style in [NumberStyle::Decimal, NumberStyle::Roman] {
+            let options = NumberOptions {
+                style,
+                ..NumberOptions::default()
+            };
+            let first = number(content, &options);
+            let second = number(&first.content, &options);
+            assert_eq!(second.content, first.content);
+            assert!(!second.summary.changed());
+            assert_eq!(second.summary.unchanged, 3);
+        }
+    }
+
+    #[test]
+    fn numbers_follow_the_outline() {
+        let content = "## A\n\n### A.1\n\n### A.2\n\n## B {: .x}\n\n#### Too deep\n\n### B.1\n";
+        let options = NumberOptions {
+            style: NumberStyle::Roman,
+            ..NumberOptions::default()
+        };
+        let processed = number(content, &options);
+        let numbers: Vec<&str> = processed
+            .content
+            .lines()
+            .filter_map(|line| SECTION_NUMBER_REGEX.find(line.trim_start_matches(['#', ' '])))
+            .map(|number| number.as_str())
+            .collect();
+        assert_eq!(
+            numbers,
+            [
+                format!("{} ", numbered("I.")),
+                format!("{} ", numbered("I.1")),
+                format!("{} ", numbered("I.2")),
+                format!("{} ", numbered("II.")),
+                format!("{} ", numbered("II.1")),
+            ]
+        );
+        assert!(processed.content.contains(" B {: #b- .x}\n"));
+    }
+
+    #[test]
+    fn renumbering_replaces_only_marked_numbers() {
+        let content = format!(
+            "## {} Second\n\n## {} First {{: .unnumbered}}\n\n## 4. Plain\n",
+            numbered("2."),
+            numbered("1."),
+        );
+        let processed = number(&content, &NumberOptions::default());
+        assert_eq!(
+            processed.content,
+            format!(
+                "## {} Second {{#second-}}\n\n## First {{: .unnumbered}}\n\n## {} 4. Plain {{#plain-}}\n",
+                numbered("1."),
+                numbered("2."),
+            )
+        );
+        assert_eq!(processed.summary.removed, 1);
+    }
+
+    #[test]
+    fn roman_numerals() {
+        assert_eq!(roman_numeral(4), "IV");
+        assert_eq!(roman_numeral(1994), "MCMXCIV");
+        assert_eq!(section_number(&[3, 2], NumberStyle::Roman), "III.2");
+        assert_eq!(section_number(&[3], NumberStyle::Decimal), "3.");
+    }
+}
@@ -0,0 +1,607 @@
+use crate::anchors::HeadingOptions;
+use crate::document::Document;
+use crate::error::{split_yaml_error, Error};
+use crate::files::source_files;
+use crate::fragments::document_anchors;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// The pages of a Jekyll site by URL, with the anchors each one has, for
+/// checking links between posts.
+pub struct Site {
+    // `url` from _config.yml, e.g. `https://example.github.io`, if set.
+    url: Option<String>,
+    // `baseurl` from _config.yml, e.g. `/blog`, or empty.
+    baseurl: String,
+    // The site root, for static files.
+    root: PathBuf,
+    // Paths below `baseurl`, e.g. `/rust/2025/01/01/post.html`. Pages whose
+    // anchors aren't known, such as HTML pages, have `None`.
+    pages: BTreeMap<String, Option<BTreeSet<String>>>,
+}
+
+// The settings of _config.yml that decide where pages end up.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct JekyllSettings {
+    url: Option<String>,
+    baseurl: Option<String>,
+    permalink: Option<String>,
+    timezone: Option<String>,
+}
+
+// The front matter keys of a post that go into its permalink.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PermalinkFrontMatter {
+    date: Option<serde_yaml::Value>,
+    category: Option<serde_yaml::Value>,
+    categories: Option<serde_yaml::Value>,
+    slug: Option<String>,
+    permalink: Option<String>,
+}
+
+// A post file name: `2025-01-31-title.md`.
+static POST_FILE_REGEX: LazyLock<Regex> =
+    static_regex!(r"^(\d{4})-(\d{1,2})-(\d{1,2})-(.+)\.(?:md|markdown|mkdown|mkdn|mkd|html)$");
+// A front matter date: `2025-01-31`, `2025-01-31 10:23:00 +0530` and the
+// like.
+static DATE_REGEX: LazyLock<Regex> = static_regex!(
+    r"^(\d{4})-(\d{1,2})-(\d{1,2})(?:[ T](\d{1,2}):(\d{2})(?::\d{2}(?:\.\d+)?)?\s*(Z|[+-]\d{2}:?\d{2})?)?$"
+);
+// A `:placeholder` in a permalink template.
+static PLACEHOLDER_REGEX: LazyLock<Regex> = static_regex!(r":([a-z_]+)");
+
+impl Site {
+    /// Reads `_config.yml` in `root` and every post in `_posts` and page at
+    /// the top of the site, giving them the anchors their headings render
+    /// to with `headings`. Returns `None` when `root` has no `_config.yml`.
+    pub fn load(root: &Path, headings: &HeadingOptions) -> Result<Option<Site>, Error> {
+        let root = if root.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            root
+        };
+        let config_path = root.join("_config.yml");
+        let config = match read(&config_path) {
+            Ok(config) => config,
+            Err(Error::Read { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            Err(err) => return Err(err),
+        };
+        let settings: JekyllSettings = serde_yaml::from_str::<Option<JekyllSettings>>(&config)
+            .map_err(|err| {
+                let (position, message) = split_yaml_error(&err);
+                Error::Config {
+                    path: config_path.clone(),
+                    position,
+                    message,
+                }
+            })?
+            .unwrap_or_default();
+
+        let mut site = Site {
+            url: settings.url.clone().filter(|url| !url.is_empty()),
+            baseurl: settings.baseurl.clone().unwrap_or_default(),
+            root: root.to_path_buf(),
+            pages: BTreeMap::new(),
+        };
+        let posts = root.join("_posts");
+        let post_files = if posts.is_dir() {
+            source_files(&posts).map_err(|source| Error::Read {
+                path: posts.clone(),
+                source,
+            })?
+        } else {
+            Vec::new()
+        };
+        for path in post_files {
+            // `_posts` also holds images and the like; only read posts.
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !POST_FILE_REGEX.is_match(&name) {
+                continue;
+            }
+            let content = read(&path)?;
+            let document = Document::parse(&content);
+            let front_matter = permalink_front_matter(&document);
+            if let Some(url) = post_url(&name, &front_matter, &settings) {
+                site.add_page(url, &path, &document, headings);
+            }
+        }
+        // Top-level pages such as index.md or about.md. Files without front
+        // matter are copied as they are and count as static files.
+        let mut entries = fs::read_dir(root)
+            .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+            .map_err(|source| Error::Read {
+                path: root.to_path_buf(),
+                source,
+            })?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = entry.path();
+            let Some(stem) = page_stem(&path) else {
+                continue;
+            };
+            let content = read(&path)?;
+            if !content.starts_with("---") {
+                continue;
+            }
+            let document = Document::parse(&content);
+            let front_matter = permalink_front_matter(&document);
+            let url = match front_matter.permalink {
+                Some(permalink) => permalink,
+                None if stem == "index" => "/".to_string(),
+                None => format!("/{}.html", stem),
+            };
+            site.add_page(url, &path, &document, headings);
+        }
+        Ok(Some(site))
+    }
+
+    fn add_page(
+        &mut self,
+        url: String,
+        path: &Path,
+        document: &Document,
+        headings: &HeadingOptions,
+    ) {
+        let is_markdown = path
+            .extension()
+            .is_some_and(|ext| ext != "html" && ext != "htm");
+        // A page whose headings have problems gets them reported when it is
+        // checked itself; until then, links into it are taken on trust.
+        let anchors = if is_markdown {
+            document_anchors(document, headings).ok()
+        } else {
+            None
+        };
+        self.pages.insert(url, anchors);
+    }
+
+    // The path of a link to this site, without the base URL, query and
+    // fragment; `None` for links elsewhere and in-page links.
+    pub(crate) fn site_path<'a>(&self, target: &'a str) -> Option<&'a str> {
+        let path = match target.split_once("//") {
+            Some((scheme, rest)) if scheme.is_empty() || scheme.ends_with(':') => {
+                let url = self.url.as_deref()?;
+                let host = url.split_once("//").map_or(url, |(_, host)| host);
+                let (target_host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                if !target_host.eq_ignore_ascii_case(host.trim_end_matches('/')) {
+                    return None;
+                }
+                path
+            }
+            _ if target.starts_with('/') => target,
+            _ => return None,
+        };
+        let path = path
+            .strip_prefix(self.baseurl.as_str())
+            .filter(|path| path.is_empty() || path.starts_with(['/', '?', '#']))?;
+        let end = path.find(['?', '#']).unwrap_or(path.len());
+        Some(&path[..end])
+    }
+
+    // The anchors of the page at `path`: `None` if there is no such page,
+    // `Some(None)` for a page whose anchors aren't known.
+    pub(crate) fn page(&self, path: &str) -> Option<Option<&BTreeSet<String>>> {
+        let path = if path.is_empty() { "/" } else { path };
+        let candidates = [
+            path.to_string(),
+            format!("{}.html", path),
+            format!("{}/", path.trim_end_matches('/')),
+            path.strip_suffix("index.html").unwrap_or(path).to_string(),
+        ];
+        for candidate in &candidates {
+            if let Some(anchors) = self.pages.get(candidate) {
+                return Some(anchors.as_ref());
+            }
+        }
+        // Static files such as images are served as they are.
+        let file = self.root.join(path.trim_start_matches('/'));
+        file.is_file().then_some(None)
+    }
+
+    // Every page URL, for suggestions.
+    pub(crate) fn urls(&self) -> impl Iterator<Item = &String> {
+        self.pages.keys()
+    }
+
+    // A full link to `path`, in the form posts use.
+    pub(crate) fn link_to(&self, path: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.url.as_deref().unwrap_or(""),
+            self.baseurl,
+            path
+        )
+    }
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|source| Error::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn permalink_front_matter(document: &Document) -> PermalinkFrontMatter {
+    // Malformed front matter is already one of the document's warnings.
+    serde_yaml::from_str::<Option<PermalinkFrontMatter>>(&document.front_matter_yaml())
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+// Jekyll's URL for a post, following the site's `permalink` setting (by
+// default `/:categories/:year/:month/:day/:title:output_ext`). `None` for
+// files Jekyll doesn't treat as posts.
+fn post_url(
+    file_name: &str,
+    front_matter: &PermalinkFrontMatter,
+    settings: &JekyllSettings,
+) -> Option<String> {
+    let file = POST_FILE_REGEX.captures(file_name)?;
+    let number = |captures: &Captures, group| captures.get(group)?.as_str().parse::<i64>().ok();
+    let date = front_matter
+        .date
+        .as_ref()
+        .and_then(|date| parse_date(&yaml_string(date)?, settings.timezone.is_some()))
+        .or_else(|| Some((number(&file, 1)?, number(&file, 2)?, number(&file, 3)?)))?;
+    let title = match &front_matter.slug {
+        Some(slug) => slug.split_whitespace().collect::<Vec<_>>().join("-"),
+        None => file.get(4)?.as_str().to_string(),
+    };
+
+    // `category` and `categories`, split on spaces, lowercased and without
+    // repeats.
+    let mut categories: Vec<String> = Vec::new();
+    for value in [&front_matter.category, &front_matter.categories]
+        .into_iter()
+        .flatten()
+    {
+        let names: Vec<String> = match value {
+            serde_yaml::Value::Sequence(items) => items.iter().filter_map(yaml_string).collect(),
+            value => yaml_string(value)
+                .map(|names| names.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+        };
+        for name in names {
+            let name = name.to_lowercase();
+            if !categories.contains(&name) {
+                categories.push(name);
+            }
+        }
+    }
+
+    let template = match front_matter
+        .permalink
+        .as_deref()
+        .or(settings.permalink.as_deref())
+    {
+        None | Some("date") => "/:categories/:year/:month/:day/:title:output_ext",
+        Some("pretty") => "/:categories/:year/:month/:day/:title/",
+        Some("ordinal") => "/:categories/:year/:y_day/:title:output_ext",
+        Some("none") => "/:categories/:title:output_ext",
+        Some(template) => template,
+    };
+    let (year, month, day) = date;
+    let url = PLACEHOLDER_REGEX.replace_all(template, |captures: &Captures| match &captures[1] {
+        "categories" => categories.join("/"),
+        "year" => year.to_string(),
+        "short_year" => format!("{:02}", year % 100),
+        "month" => format!("{:02}", month),
+        "i_month" => month.to_string(),
+        "day" => format!("{:02}", day),
+        "i_day" => day.to_string(),
+        "y_day" => format!(
+            "{:03}",
+            days_from_civil(year, month, day) - days_from_civil(year, 1, 1) + 1
+        ),
+        "title" | "slug" => title.clone(),
+        "output_ext" => ".html".to_string(),
+        _ => captures[0].to_string(),
+    });
+    // Empty placeholders leave double slashes, which Jekyll collapses.
+    let mut collapsed = String::from("/");
+    for part in url.split('/').filter(|part| !part.is_empty()) {
+        collapsed.push_str(part);
+        collapsed.push('/');
+    }
+    if !url.ends_with('/') {
+        collapsed.pop();
+    }
+    Some(collapsed)
+}
+
+// A post's year, month and day. GitHub Pages builds in UTC, so a date with
+// an offset moves to its UTC day, unless the site sets its own timezone.
+fn parse_date(date: &str, site_has_timezone: bool) -> Option<(i64, i64, i64)> {
+    let captures = DATE_REGEX.captures(date.trim())?;
+    let number = |group| captures.get(group)?.as_str().parse::<i64>().ok();
+    let (year, month, day) = (number(1)?, number(2)?, number(3)?);
+    let (Some(hour), Some(minute), Some(offset)) = (number(4), number(5), captures.get(6)) else {
+        return Some((year, month, day));
+    };
+    if site_has_timezone {
+        return Some((year, month, day));
+    }
+    let offset = offset.as_str().replace(':', "");
+    let offset_minutes = match offset.as_str() {
+        "Z" => 0,
+        offset => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let hours: i64 = offset[1..3].parse().ok()?;
+            let minutes: i64 = offset[3..5].parse().ok()?;
+            sign * (hours * 60 + minutes)
+        }
+    };
+    let day_shift = (hour * 60 + minute - offset_minutes).div_euclid(24 * 60);
+    Some(civil_from_days(
+        days_from_civil(year, month, day) + day_shift,
+    ))
+}
+
+fn yaml_string(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(text) => Some(text.clone()),
+        serde_yaml::Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// The date `days` after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// The name of a page at the top of the site, without its extension.
+fn page_stem(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    if !path.is_file() || name.starts_with(['_', '.']) {
+        return None;
+    }
+    let (stem, ext) = name.rsplit_once('.')?;
+    matches!(ext, "md" | "markdown" | "html").then(|| stem.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::tests::Scratch;
+    use crate::fragments::{check_links, LinkCheckOptions};
+
+    fn url(file_name: &str, front_matter: &str, permalink: Option<&str>) -> Option<String> {
+        let front_matter = serde_yaml::from_str(front_matter).unwrap();
+        let settings = JekyllSettings {
+            permalink: permalink.map(str::to_string),
+            ..JekyllSettings::default()
+        };
+        post_url(file_name, &front_matter, &settings)
+    }
+
+    #[test]
+    fn post_urls() {
+        let post = "2025-01-31-hello-world.md";
+        assert_eq!(
+            url(post, "{}", None).unwrap(),
+            "/2025/01/31/hello-world.html"
+        );
+        assert_eq!(
+            url(post, "{}", Some("pretty")).unwrap(),
+            "/2025/01/31/hello-world/"
+        );
+        assert_eq!(
+            url(post, "{}", Some("ordinal")).unwrap(),
+            "/2025/031/hello-world.html"
+        );
+        assert_eq!(url(post, "{}", Some("none")).unwrap(), "/hello-world.html");
+        assert_eq!(
+            url(post, "{}", Some("/:short_year/:i_month/:i_day/:slug")).unwrap(),
+            "/25/1/31/hello-world"
+        );
+        assert_eq!(url("notes.md", "{}", None), None);
+    }
+
+    #[test]
+    fn post_urls_from_front_matter() {
+        let post = "2025-01-31-hello-world.md";
+        assert_eq!(
+            url(post, "category: Rust\ncategories: [web, rust]", None).unwrap(),
+            "/rust/web/2025/01/31/hello-world.html"
+        );
+        assert_eq!(
+            url(post, "categories: Rust Web", Some("none")).unwrap(),
+            "/rust/web/hello-world.html"
+        );
+        assert_eq!(
+            url(post, "slug: second try", None).unwrap(),
+            "/2025/01/31/second-try.html"
+        );
+        assert_eq!(
+            url(post, "date: 2025-02-03 10:00:00", None).unwrap(),
+            "/2025/02/03/hello-world.html"
+        );
+        assert_eq!(
+            url(post, "permalink: /:title/", Some("pretty")).unwrap(),
+            "/hello-world/"
+        );
+    }
+
+    #[test]
+    fn dates_move_to_utc() {
+        assert_eq!(parse_date("2025-01-31", false), Some((2025, 1, 31)));
+        assert_eq!(
+            parse_date("2025-01-01 02:00:00 +0530", false),
+            Some((2024, 12, 31))
+        );
+        assert_eq!(
+            parse_date("2025-02-28 23:00 -02:00", false),
+            Some((2025, 3, 1))
+        );
+        assert_eq!(
+            parse_date("2024-12-31T23:59:59Z", false),
+            Some((2024, 12, 31))
+        );
+        // A site with its own timezone keeps the date as written.
+        assert_eq!(
+            parse_date("2025-01-01 02:00:00 +0530", true),
+            Some((2025, 1, 1))
+        );
+        assert_eq!(parse_date("January 1st", false), None);
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        for days in (-800_000..800_000).step_by(97) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    fn site(root: &Path) -> Site {
+        let pages = ["/", "/about.html", "/2025/01/31/post.html", "/pretty/"]
+            .into_iter()
+            .map(|url| (url.to_string(), Some(BTreeSet::new())))
+            .collect();
+        Site {
+            url: Some("https://example.github.io".to_string()),
+            baseurl: "/blog".to_string(),
+            root: root.to_path_buf(),
+            pages,
+        }
+    }
+
+    #[test]
+    fn site_paths() {
+        let site = site(Path::new("."));
+        let path = |target| site.site_path(target);
+        assert_eq!(
+            path("https://example.github.io/blog/2025/01/31/post.html#intro"),
+            Some("/2025/01/31/post.html")
+        );
+        assert_eq!(path("//EXAMPLE.github.io/blog/"), Some("/"));
+        assert_eq!(path("/blog/about.html?ref=home"), Some("/about.html"));
+        assert_eq!(path("/blog"), Some(""));
+        assert_eq!(path("https://example.org/blog/about.html"), None);
+        assert_eq!(path("/blogroll.html"), None);
+        assert_eq!(path("/about.html"), None);
+        assert_eq!(path("about.html"), None);
+        assert_eq!(path("#intro"), None);
+    }
+
+    #[test]
+    fn pages() {
+        let scratch = Scratch::new("pages");
+        scratch.write("images/photo.png", "");
+        let site = site(&scratch.0);
+        assert!(matches!(site.page(""), Some(Some(_))));
+        assert!(matches!(site.page("/index.html"), Some(Some(_))));
+        assert!(matches!(site.page("/about"), Some(Some(_))));
+        assert!(matches!(site.page("/pretty"), Some(Some(_))));
+        assert!(matches!(site.page("/images/photo.png"), Some(None)));
+        assert!(site.page("/missing.html").is_none());
+        assert_eq!(
+            site.link_to("/about.html"),
+            "https://example.github.io/blog/about.html"
+        );
+    }
+
+    #[test]
+    fn links_between_posts() {
+        let scratch = Scratch::new("site");
+        scratch.write(
+            "_config.yml",
+            "url: https://example.github.io\nbaseurl: /blog\n",
+        );
+        scratch.write(
+            "_posts/2025-01-31-lifetimes.md",
+            "---\ntitle: Lifetimes\n---\n## Part one: variable bindings\n",
+        );
+        scratch.write("about.md", "---\ntitle: About\n---\n# About\n");
+        // Images and notes in `_posts` are not read.
+        fs::write(scratch.0.join("_posts/photo.png"), [0x89, 0xff, 0xfe]).unwrap();
+        scratch.write("_posts/notes.txt", "");
+
+        let site = Site::load(&scratch.0, &HeadingOptions::default())
+            .unwrap()
+            .unwrap();
+        let options = LinkCheckOptions {
+            site: Some(site),
+            ..LinkCheckOptions::default()
+        };
+        let post = "https://example.github.io/blog/2025/01/31/lifetimes.html";
+        let content = format!(
+            "[a]({post}#part-one-variable-bindings) [b](/blog/about.html#about) \
+             [c]({post}#part-one-variable-binding) [d](/blog/abuot.html)\n"
+        );
+        let problems: Vec<String> = check_links(&Document::parse(&content), &options)
+            .unwrap()
+            .iter()
+            .map(|problem| format!("{} ({})", problem, problem.help().unwrap_or_default()))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                format!(
+                    "link to `{post}#part-one-variable-binding` does not match any anchor \
+                     (did you mean `{post}#part-one-variable-bindings`?)"
+                ),
+                "link to `/blog/abuot.html` does not match any page of the site \
+                 (did you mean `https://example.github.io/blog/about.html`?)"
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn unreadable_posts_are_named() {
+        let scratch = Scratch::new("unreadable");
+        scratch.write("_config.yml", "title: Blog\n");
+        fs::create_dir_all(scratch.0.join("_posts")).unwrap();
+        let post = scratch.0.join("_posts/2025-01-31-binary.md");
+        fs::write(&post, [0x89, 0xff, 0xfe]).unwrap();
+        let Err(err) = Site::load(&scratch.0, &HeadingOptions::default()) else {
+            panic!("the post was read");
+        };
+        assert_eq!(err.path(), Some(post.as_path()));
+        assert!(
+            Site::load(&scratch.0.join("_posts"), &HeadingOptions::default())
+                .unwrap()
+                .is_none()
+        );
+    }
+}
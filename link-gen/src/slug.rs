@@ -0,0 +1,343 @@
+use crate::error::Error;
+use crate::link::LinkTemplate;
+use deunicode::deunicode;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Turns heading text into the id a particular renderer gives that heading.
+pub trait Slugger: Send + Sync {
+    /// The id for a heading before repeated ids are numbered.
+    fn slug(&self, heading_text: &str, level: usize) -> String;
+}
+
+/// kramdown's own `auto_ids`, used for the posts on GitHub Pages.
+pub struct KramdownSlugger;
+
+// kramdown falls back to `section` when nothing is left of the heading;
+// `AnchorIds` reports that as an error instead, so it is not reproduced here.
+impl Slugger for KramdownSlugger {
+    fn slug(&self, heading_text: &str, _level: usize) -> String {
+        generate_anchor(heading_text)
+    }
+}
+
+/// kramdown-parser-gfm's `generate_gfm_header_id`, which matches the ids
+/// GitHub gives headings in rendered files such as `Readme.md`.
+pub struct GfmSlugger;
+
+impl Slugger for GfmSlugger {
+    fn slug(&self, heading_text: &str, _level: usize) -> String {
+        generate_gfm_anchor(heading_text)
+    }
+}
+
+/// mdBook's `normalize_id`.
+pub struct MdBookSlugger;
+
+impl Slugger for MdBookSlugger {
+    fn slug(&self, heading_text: &str, _level: usize) -> String {
+        generate_mdbook_anchor(heading_text)
+    }
+}
+
+/// A user supplied pattern such as `sec-{level}-{slug}`, where `{slug}` is the
+/// heading text lowercased with every run of other characters collapsed into
+/// a single hyphen.
+pub struct TemplateSlugger {
+    template: String,
+}
+
+impl Slugger for TemplateSlugger {
+    fn slug(&self, heading_text: &str, level: usize) -> String {
+        let slug = heading_text
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        self.template
+            .replace("{slug}", &slug)
+            .replace("{level}", &level.to_string())
+    }
+}
+
+/// Looks up a slugger by the name used on the command line and in front
+/// matter: `kramdown`, `gfm`, `mdbook` or `template:<pattern>`.
+pub fn slugger_by_name(name: &str) -> Option<Box<dyn Slugger>> {
+    match name {
+        "kramdown" => Some(Box::new(KramdownSlugger)),
+        "gfm" => Some(Box::new(GfmSlugger)),
+        "mdbook" => Some(Box::new(MdBookSlugger)),
+        _ => name.strip_prefix("template:").map(|template| {
+            Box::new(TemplateSlugger {
+                template: template.to_string(),
+            }) as Box<dyn Slugger>
+        }),
+    }
+}
+
+// Hands out header ids for one document, numbering repeated ids the way
+// the renderers do so the second "Example" heading links to `example-1`.
+// kramdown only remembers base ids, so a later heading literally named
+// "Example 1" may still collide with a generated `example-1`; every
+// renderer we target behaves the same, so that is reproduced on purpose.
+//
+// Ids that would be empty or clash with an earlier heading are errors: the
+// renderer would silently produce a link that goes nowhere or to the wrong
+// section.
+pub(crate) struct AnchorIds<'a> {
+    slugger: &'a dyn Slugger,
+    transliterate: bool,
+    // The header link ends up in the rendered heading.
+    link: &'a LinkTemplate,
+    used: HashMap<String, usize>,
+    // Every id handed out so far and the line of the heading that got it.
+    claimed: HashMap<String, usize>,
+}
+
+impl<'a> AnchorIds<'a> {
+    pub(crate) fn new(
+        slugger: &'a dyn Slugger,
+        transliterate: bool,
+        link: &'a LinkTemplate,
+    ) -> Self {
+        AnchorIds {
+            slugger,
+            transliterate,
+            link,
+            used: HashMap::new(),
+            claimed: HashMap::new(),
+        }
+    }
+
+    // `position` is the line, column and width of the heading text, for
+    // errors.
+    pub(crate) fn next(
+        &mut self,
+        heading_text: &str,
+        level: usize,
+        position: (usize, usize, usize),
+        linked: bool,
+    ) -> Result<String, Error> {
+        let plain_text = if self.transliterate {
+            deunicode(heading_text)
+        } else {
+            heading_text.to_string()
+        };
+        // Headings without a link are only counted, so the numbering of
+        // repeated ids matches the renderer; their ids are not checked.
+        let rendered_text = if linked {
+            self.link.rendered_text(&plain_text)
+        } else {
+            plain_text
+        };
+        let id = self.slugger.slug(&rendered_text, level);
+        let (line, column, width) = position;
+        if linked && !id.chars().any(char::is_alphanumeric) {
+            return Err(Error::EmptyAnchor {
+                line,
+                column,
+                width,
+                heading: heading_text.to_string(),
+            });
+        }
+
+        let count = self.used.entry(id.clone()).or_insert(0);
+        *count += 1;
+        let id = if *count > 1 {
+            format!("{}-{}", id, *count - 1)
+        } else {
+            id
+        };
+        if linked {
+            self.claim(&id, position)?;
+        } else {
+            self.claimed.entry(id.clone()).or_insert(line);
+        }
+        Ok(id)
+    }
+
+    // Records an id given to the heading at `line`, explicit or generated.
+    pub(crate) fn claim(
+        &mut self,
+        id: &str,
+        (line, column, width): (usize, usize, usize),
+    ) -> Result<(), Error> {
+        if let Some(&first_line) = self.claimed.get(id) {
+            return Err(Error::DuplicateId {
+                line,
+                column,
+                width,
+                id: id.to_string(),
+                first_line,
+            });
+        }
+        self.claimed.insert(id.to_string(), line);
+        Ok(())
+    }
+}
+
+/// Percent-encodes an id for an `href` fragment. The id itself keeps its
+/// Unicode characters; only the link needs escaping.
+pub fn encode_fragment(id: &str) -> String {
+    let mut encoded = String::with_capacity(id.len());
+    for byte in id.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$()*+,;=:@/?".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// kramdown's `basic_generate_id`: drop everything before the first ASCII
+/// letter, keep only ASCII alphanumerics, spaces and hyphens, turn spaces
+/// into hyphens and lowercase the result.
+pub fn generate_anchor(heading_text: &str) -> String {
+    heading_text
+        .trim_start_matches(|c: char| !c.is_ascii_alphabetic())
+        .chars()
+        .filter_map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' => Some(c),
+            'A'..='Z' => Some(c.to_ascii_lowercase()),
+            ' ' => Some('-'),
+            _ => None,
+        })
+        .collect()
+}
+
+static GFM_NON_WORD_REGEX: LazyLock<Regex> = static_regex!(r"[^\p{L}\p{M}\p{Nd}\p{Pc}\- \t]");
+
+/// kramdown-parser-gfm's `generate_gfm_header_id`: lowercase, drop every
+/// character that is not a word character, hyphen, space or tab, then turn
+/// spaces and tabs into hyphens.
+pub fn generate_gfm_anchor(heading_text: &str) -> String {
+    GFM_NON_WORD_REGEX
+        .replace_all(&heading_text.to_lowercase(), "")
+        .replace([' ', '\t'], "-")
+}
+
+/// mdBook's `normalize_id`: keep alphanumerics, underscores and hyphens
+/// (lowercasing ASCII only), turn whitespace into hyphens, drop the rest.
+pub fn generate_mdbook_anchor(heading_text: &str) -> String {
+    heading_text
+        .trim()
+        .chars()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' {
+                Some(c.to_ascii_lowercase())
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kramdown_ids() {
+        assert_eq!(generate_anchor("Hello World"), "hello-world");
+        // Everything before the first letter goes, so do non-ASCII letters.
+        assert_eq!(generate_anchor("1. Getting Started!"), "getting-started");
+        assert_eq!(generate_anchor("Café au lait"), "caf-au-lait");
+        assert_eq!(generate_anchor("Rust & Go: Compared"), "rust--go-compared");
+        assert_eq!(generate_anchor("snake_case"), "snakecase");
+        assert_eq!(generate_anchor("Principle 🔗"), "principle-");
+    }
+
+    #[test]
+    fn gfm_ids() {
+        assert_eq!(generate_gfm_anchor("Hello, World!"), "hello-world");
+        assert_eq!(generate_gfm_anchor("Café au lait"), "café-au-lait");
+        assert_eq!(
+            generate_gfm_anchor("snake_case and kebab-case"),
+            "snake_case-and-kebab-case"
+        );
+        assert_eq!(generate_gfm_anchor("Rust & Go"), "rust--go");
+        assert_eq!(generate_gfm_anchor("1. Intro"), "1-intro");
+    }
+
+    #[test]
+    fn mdbook_ids() {
+        assert_eq!(generate_mdbook_anchor(" Hello World! "), "hello-world");
+        // Only ASCII is lowercased.
+        assert_eq!(generate_mdbook_anchor("Über uns"), "Über-uns");
+    }
+
+    #[test]
+    fn template_ids() {
+        let slugger = slugger_by_name("template:sec-{level}-{slug}").unwrap();
+        assert_eq!(slugger.slug("Hello, World!", 2), "sec-2-hello-world");
+        assert!(slugger_by_name("nope").is_none());
+    }
+
+    #[test]
+    fn repeated_ids_are_numbered() {
+        let link = LinkTemplate::default();
+        let mut ids = AnchorIds::new(&KramdownSlugger, false, &link);
+        // The id includes the rendered 🔗, as kramdown sees it.
+        assert_eq!(ids.next("Example", 2, (1, 1, 7), true).unwrap(), "example-");
+        assert_eq!(
+            ids.next("Example", 2, (3, 1, 7), true).unwrap(),
+            "example--1"
+        );
+        assert_eq!(
+            ids.next("Example", 2, (5, 1, 7), true).unwrap(),
+            "example--2"
+        );
+    }
+
+    #[test]
+    fn unlinked_headings_take_up_their_id() {
+        let link = LinkTemplate::default();
+        let mut ids = AnchorIds::new(&KramdownSlugger, false, &link);
+        assert_eq!(ids.next("Example", 1, (1, 1, 7), false).unwrap(), "example");
+        assert_eq!(
+            ids.next("Example", 1, (3, 1, 7), false).unwrap(),
+            "example-1"
+        );
+    }
+
+    #[test]
+    fn empty_and_duplicate_ids_are_errors() {
+        let link = LinkTemplate::default();
+        let mut ids = AnchorIds::new(&KramdownSlugger, false, &link);
+        assert!(matches!(
+            ids.next("!!!", 2, (1, 4, 3), true),
+            Err(Error::EmptyAnchor {
+                line: 1,
+                column: 4,
+                ..
+            })
+        ));
+        ids.claim("intro", (3, 1, 5)).unwrap();
+        assert!(matches!(
+            ids.claim("intro", (7, 1, 5)),
+            Err(Error::DuplicateId {
+                line: 7,
+                first_line: 3,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn transliterated_ids() {
+        let link = LinkTemplate::default();
+        let mut ids = AnchorIds::new(&KramdownSlugger, true, &link);
+        assert_eq!(ids.next("Café", 2, (1, 1, 4), false).unwrap(), "cafe");
+    }
+
+    #[test]
+    fn fragments_are_percent_encoded() {
+        assert_eq!(encode_fragment("café-1"), "caf%C3%A9-1");
+        assert_eq!(encode_fragment("a_b c"), "a_b%20c");
+    }
+}
@@ -0,0 +1,614 @@
+use crate::anchors::{
+    scan_headings, scan_rendered_headings, Check, HeadingOptions, HeadingSummary, Pass,
+    ProcessedDocument, ScannedHeading,
+};
+use crate::document::{BlockKind, Document};
+use crate::error::Error;
+use crate::heading::has_class;
+use crate::slug::encode_fragment;
+use regex::Regex;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+const TOC_START: &str = "<!-- toc -->";
+const TOC_END: &str = "<!-- tocstop -->";
+
+/// Settings for one run of `generate_toc` or `validate_toc`.
+pub struct TocOptions {
+    /// How anchors are generated; the same settings as for the header links,
+    /// so the entries point where the links do. Generated entries use the ids
+    /// headings get once they have their link, validation the ids they have
+    /// now.
+    pub headings: HeadingOptions,
+    /// Only headings from `min_level` to `max_level` are listed.
+    pub min_level: usize,
+    pub max_level: usize,
+    /// Headings to leave out, by text (ignoring case) or by anchor. Headings
+    /// with kramdown's `{: .no_toc}` class are always left out.
+    pub exclude: Vec<String>,
+}
+
+impl Default for TocOptions {
+    fn default() -> Self {
+        TocOptions {
+            headings: HeadingOptions::default(),
+            min_level: 2,
+            max_level: 3,
+            exclude: Vec::new(),
+        }
+    }
+}
+
+// Regenerates the table of contents.
+impl Pass for TocOptions {
+    fn apply(&self, document: &Document) -> Result<ProcessedDocument, Error> {
+        generate_toc(document, self)
+    }
+}
+
+// Compares the table of contents with the headings.
+impl Check for TocOptions {
+    fn check(&self, document: &Document) -> Result<Vec<Error>, Error> {
+        validate_toc(document, self)
+    }
+}
+
+// A list item marker at the start of a line.
+static LIST_ITEM_REGEX: LazyLock<Regex> = static_regex!(r"^[ \t]*(?:[-*+]|\d{1,9}[.)])(?:[ \t]|$)");
+// The fragment a table of contents entry links to.
+static ENTRY_TARGET_REGEX: LazyLock<Regex> = static_regex!(r"\]\(#([^)\s]*)\)");
+
+/// Regenerates the table of contents of a document: the list between
+/// `<!-- toc -->` and `<!-- tocstop -->`, or else the list under a "Table of
+/// Contents" heading. Documents with neither are left as they are.
+///
+/// The summary counts entries rather than headings; entries whose target
+/// stayed but whose text or nesting changed are `updated`.
+pub fn generate_toc(document: &Document, options: &TocOptions) -> Result<ProcessedDocument, Error> {
+    let lines = &document.lines;
+    let headings = scan_headings(document, &options.headings)?;
+    let Some((range, between_markers)) = find_toc(document, &headings) else {
+        return Ok(ProcessedDocument {
+            content: document.splice(0..0, &[]),
+            summary: HeadingSummary::default(),
+        });
+    };
+
+    // Keep the spacing of the old list: if its top-level entries are
+    // separated by blank lines, so are the new ones.
+    let old_lines = &lines[range.clone()];
+    let first = old_lines.iter().position(|line| !line.trim().is_empty());
+    let last = old_lines.iter().rposition(|line| !line.trim().is_empty());
+    let loose = match (first, last) {
+        (Some(first), Some(last)) => old_lines[first..last]
+            .iter()
+            .any(|line| line.trim().is_empty()),
+        _ => false,
+    };
+
+    let outline = outline(&headings, options);
+    let mut entries = Vec::new();
+    for item in &outline {
+        if loose && item.depth == 1 && !entries.is_empty() {
+            entries.push(String::new());
+        }
+        entries.push(item.entry());
+    }
+
+    let mut summary = HeadingSummary::default();
+    let old_targets: Vec<&str> = parse_entries(lines, range.clone())
+        .into_iter()
+        .map(|entry| entry.target)
+        .collect();
+    for item in &outline {
+        if old_lines.contains(&item.entry().as_str()) {
+            summary.unchanged += 1;
+        } else if old_targets.contains(&item.target.as_str()) {
+            summary.updated += 1;
+        } else {
+            summary.added += 1;
+        }
+    }
+    summary.removed = old_targets
+        .iter()
+        .filter(|&&target| !outline.iter().any(|item| item.target == target))
+        .count();
+
+    // Blank lines around the list keep it from running into the markers or
+    // the paragraph after it.
+    let mut replacement = Vec::new();
+    if between_markers || !entries.is_empty() {
+        replacement.push(String::new());
+        replacement.append(&mut entries);
+        let followed_by_text = lines
+            .get(range.end)
+            .is_some_and(|line| !line.trim().is_empty());
+        if between_markers || followed_by_text {
+            replacement.push(String::new());
+        }
+    }
+    Ok(ProcessedDocument {
+        content: document.splice(range, &replacement),
+        summary,
+    })
+}
+
+/// Compares a hand-written table of contents with the headings it should
+/// list: entries must link to existing anchors, list every heading
+/// `generate_toc` would, and follow the order and nesting of the document.
+/// Documents without a table of contents pass.
+pub fn validate_toc(document: &Document, options: &TocOptions) -> Result<Vec<Error>, Error> {
+    let headings = scan_rendered_headings(document, &options.headings)?;
+    let Some((range, _)) = find_toc(document, &headings) else {
+        return Ok(Vec::new());
+    };
+    let entries = parse_entries(&document.lines, range);
+    let outline = outline(&headings, options);
+    let mut problems = Vec::new();
+
+    // Where each entry's heading is in the outline, for entries that
+    // belong there at all.
+    let mut placed = Vec::new();
+    for entry in &entries {
+        let exists = headings.iter().any(|scanned| {
+            let anchor = &scanned.heading.anchor;
+            entry.target == encode_fragment(anchor) || entry.target == anchor
+        });
+        if !exists {
+            problems.push(Error::BrokenTocEntry {
+                line: entry.line + 1,
+                column: entry.target_column,
+                width: entry.target.chars().count() + 1,
+                target: entry.target.to_string(),
+            });
+        } else if let Some(index) = outline.iter().position(|item| item.target == entry.target) {
+            placed.push((entry, index));
+        }
+    }
+
+    for item in &outline {
+        if !entries.iter().any(|entry| entry.target == item.target) {
+            problems.push(Error::MissingTocEntry {
+                line: item.position.0,
+                column: item.position.1,
+                width: item.position.2,
+                heading: item.text.to_string(),
+                entry: item.entry().trim_start().to_string(),
+            });
+        }
+    }
+
+    // Entries in the longest run that is in document order are taken as
+    // right, so moving one entry reports just that entry.
+    let in_order = longest_increasing(&placed.iter().map(|&(_, index)| index).collect::<Vec<_>>());
+    for (position, &(entry, index)) in placed.iter().enumerate() {
+        let item = &outline[index];
+        if !in_order.contains(&position) {
+            problems.push(Error::TocEntryOrder {
+                line: entry.line + 1,
+                column: entry.column,
+                width: entry.width,
+                target: entry.target.to_string(),
+                heading_line: item.position.0,
+            });
+        } else if entry.depth != item.depth {
+            problems.push(Error::TocEntryNesting {
+                line: entry.line + 1,
+                column: entry.column,
+                width: entry.width,
+                target: entry.target.to_string(),
+                depth: entry.depth,
+                expected: item.depth,
+            });
+        }
+    }
+    problems.sort_by_key(|problem| problem.position());
+    Ok(problems)
+}
+
+// The lines of the table of contents, and whether they sit between markers
+// rather than under a heading.
+fn find_toc(document: &Document, headings: &[ScannedHeading]) -> Option<(Range<usize>, bool)> {
+    if let Some(range) = find_markers(document) {
+        return Some((range, true));
+    }
+    let start = headings
+        .iter()
+        .find(|scanned| is_toc_heading(&scanned.heading.text))?
+        .heading
+        .lines
+        .end;
+    Some((start..list_end(document, start), false))
+}
+
+pub(crate) fn is_toc_heading(text: &str) -> bool {
+    text.trim().eq_ignore_ascii_case("table of contents")
+}
+
+// The lines between the first pair of markers outside code blocks.
+fn find_markers(document: &Document) -> Option<Range<usize>> {
+    let marker_lines = |marker: &'static str| {
+        (0..document.lines.len()).filter(move |&index| {
+            document.lines[index].trim() == marker
+                && !matches!(
+                    document.kinds[index],
+                    BlockKind::FencedCode | BlockKind::IndentedCode
+                )
+        })
+    };
+    let start = marker_lines(TOC_START).next()?;
+    let end = marker_lines(TOC_END).find(|&end| end > start)?;
+    Some(start + 1..end)
+}
+
+// The end of the list right under the table of contents heading: its items,
+// their indented continuation lines and the blank lines between them. Where
+// there is no list yet, it ends where it starts.
+fn list_end(document: &Document, start: usize) -> usize {
+    let mut end = start;
+    for (index, line) in document.lines.iter().enumerate().skip(start) {
+        if matches!(
+            document.kinds[index],
+            BlockKind::Heading | BlockKind::SetextHeading | BlockKind::FencedCode
+        ) {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let continues_item = end > start && line.starts_with([' ', '\t']);
+        if !LIST_ITEM_REGEX.is_match(line) && !continues_item {
+            break;
+        }
+        end = index + 1;
+    }
+    end
+}
+
+// A heading as the table of contents lists it.
+struct OutlineItem<'a> {
+    text: &'a str,
+    target: String,
+    // 1 for top-level entries.
+    depth: usize,
+    // 1-based line and column of the heading text, and its width.
+    position: (usize, usize, usize),
+}
+
+impl OutlineItem<'_> {
+    fn entry(&self) -> String {
+        format!(
+            "{}- [{}](#{})",
+            "  ".repeat(self.depth - 1),
+            escape_link_text(self.text),
+            self.target
+        )
+    }
+}
+
+// The headings the table of contents lists, nested under the closest
+// listed heading of a lower level.
+fn outline<'a>(headings: &'a [ScannedHeading], options: &TocOptions) -> Vec<OutlineItem<'a>> {
+    let mut items = Vec::new();
+    // Levels of the items the next one can nest under.
+    let mut parents: Vec<usize> = Vec::new();
+    for scanned in headings {
+        let heading = &scanned.heading;
+        let excluded = options.exclude.iter().any(|excluded| {
+            excluded.eq_ignore_ascii_case(&heading.text) || *excluded == heading.anchor
+        });
+        if !(options.min_level..=options.max_level).contains(&heading.level)
+            || excluded
+            || is_toc_heading(&heading.text)
+            || scanned
+                .attributes
+                .is_some_and(|attributes| has_class(attributes, "no_toc"))
+        {
+            continue;
+        }
+        while parents.last().is_some_and(|&level| level >= heading.level) {
+            parents.pop();
+        }
+        parents.push(heading.level);
+        items.push(OutlineItem {
+            text: &heading.text,
+            target: encode_fragment(&heading.anchor),
+            depth: parents.len(),
+            position: (
+                scanned.line.line + 1,
+                scanned.line.prefix.chars().count() + 1,
+                scanned.line.text.chars().count(),
+            ),
+        });
+    }
+    items
+}
+
+// An entry of an existing table of contents.
+struct TocEntry<'a> {
+    // 0-based, like the document's lines.
+    line: usize,
+    // 1-based columns of the list marker and of the `#` of the link.
+    column: usize,
+    target_column: usize,
+    // Characters from the list marker to the end of the line.
+    width: usize,
+    target: &'a str,
+    // 1 for top-level entries.
+    depth: usize,
+}
+
+// The linked list items in `range`, nested by their indentation.
+fn parse_entries<'a>(lines: &[&'a str], range: Range<usize>) -> Vec<TocEntry<'a>> {
+    let mut entries = Vec::new();
+    // Indentation of the items the next one can nest under.
+    let mut parents: Vec<usize> = Vec::new();
+    for index in range {
+        let line = lines[index];
+        if !LIST_ITEM_REGEX.is_match(line) {
+            continue;
+        }
+        let indent_chars = line.len() - line.trim_start().len();
+        let indent: usize = line[..indent_chars]
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum();
+        while parents.last().is_some_and(|&parent| parent >= indent) {
+            parents.pop();
+        }
+        parents.push(indent);
+        let Some(target) = ENTRY_TARGET_REGEX.captures(line).and_then(|c| c.get(1)) else {
+            continue;
+        };
+        entries.push(TocEntry {
+            line: index,
+            column: line[..indent_chars].chars().count() + 1,
+            // The `#` is just before the captured fragment.
+            target_column: line[..target.start()].chars().count(),
+            width: line.trim().chars().count(),
+            target: target.as_str(),
+            depth: parents.len(),
+        });
+    }
+    entries
+}
+
+// Indices of a longest strictly increasing subsequence of `values`.
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    // For each value, the length of the longest run ending in it and the
+    // index of the value before it in that run.
+    let mut lengths = vec![1; values.len()];
+    let mut previous = vec![None; values.len()];
+    for i in 0..values.len() {
+        for j in 0..i {
+            if values[j] < values[i] && lengths[j] + 1 > lengths[i] {
+                lengths[i] = lengths[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+    let mut run = Vec::new();
+    let mut current = (0..values.len()).max_by_key(|&i| (lengths[i], std::cmp::Reverse(i)));
+    while let Some(i) = current {
+        run.push(i);
+        current = previous[i];
+    }
+    run.reverse();
+    run
+}
+
+// Heading text is plain text; keep Markdown from reading anything into it.
+fn escape_link_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anchors::process_markdown_headings;
+
+    const HEADINGS: &str = "## One\n\n### One a\n\n## Two\n\n## Three\n";
+
+    fn with_toc(entries: &str) -> String {
+        format!(
+            "# Post\n\n<!-- toc -->\n\n{}\n<!-- tocstop -->\n\n{}",
+            entries, HEADINGS
+        )
+    }
+
+    fn validate(content: &str) -> Vec<Error> {
+        validate_toc(&Document::parse(content), &TocOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn matching_toc() {
+        let content =
+            with_toc("- [One](#one)\n  - [One a](#one-a)\n- [Two](#two)\n- [Three](#three)");
+        assert!(validate(&content).is_empty());
+        assert!(validate(HEADINGS).is_empty(), "no table of contents");
+    }
+
+    #[test]
+    fn linked_headings() {
+        let content = with_toc("- [One](#one)");
+        let linked = process_markdown_headings(&Document::parse(&content), &Default::default())
+            .unwrap()
+            .content;
+        let generated = generate_toc(&Document::parse(&linked), &TocOptions::default())
+            .unwrap()
+            .content;
+        assert!(generated.contains("- [One](#one-)\n"));
+        assert!(validate(&generated).is_empty());
+    }
+
+    #[test]
+    fn broken_and_missing_entries() {
+        let content = with_toc("- [One](#one)\n  - [One a](#one-a)\n- [Tow](#tow)");
+        let problems = validate(&content);
+        assert!(matches!(
+            &problems[..],
+            [
+                Error::BrokenTocEntry {
+                    line: 7,
+                    column: 9,
+                    width: 4,
+                    target,
+                },
+                Error::MissingTocEntry { line: 14, heading: two, entry: two_entry, .. },
+                Error::MissingTocEntry { line: 16, heading: three, entry: three_entry, .. },
+            ] if target == "tow"
+                && two == "Two"
+                && two_entry == "- [Two](#two)"
+                && three == "Three"
+                && three_entry == "- [Three](#three)"
+        ));
+    }
+
+    #[test]
+    fn order_and_nesting() {
+        // Moving "Three" to the top reports only "Three", not the entries
+        // it moved ahead of.
+        let content =
+            with_toc("- [Three](#three)\n- [One](#one)\n- [One a](#one-a)\n- [Two](#two)");
+        let problems = validate(&content);
+        assert!(matches!(
+            &problems[..],
+            [
+                Error::TocEntryOrder { line: 5, column: 1, target: three, heading_line: 17, .. },
+                Error::TocEntryNesting { line: 7, target: one_a, depth: 1, expected: 2, .. },
+            ] if three == "three" && one_a == "one-a"
+        ));
+        assert_eq!(
+            problems[1].help().unwrap(),
+            "indent it by 2 spaces to match the outline"
+        );
+    }
+
+    #[test]
+    fn longest_run_in_order() {
+        assert_eq!(longest_increasing(&[3, 0, 1, 2]), [1, 2, 3]);
+        assert_eq!(longest_increasing(&[0, 2, 1, 3]), [0, 1, 3]);
+        assert_eq!(longest_increasing(&[1, 0]), [0]);
+        assert!(longest_increasing(&[]).is_empty());
+    }
+
+    fn generate(content: &str, options: &TocOptions) -> ProcessedDocument {
+        generate_toc(&Document::parse(content), options).unwrap()
+    }
+
+    #[test]
+    fn generates_between_markers() {
+        let generated = generate(&with_toc(""), &TocOptions::default());
+        assert_eq!(
+            generated.content,
+            "# Post\n\n<!-- toc -->\n\n\
+             - [One](#one-)\n  - [One a](#one-a-)\n- [Two](#two-)\n- [Three](#three-)\n\n\
+             <!-- tocstop -->\n\n## One\n\n### One a\n\n## Two\n\n## Three\n"
+        );
+        assert_eq!(generated.summary.added, 4);
+
+        // A second run finds nothing to do.
+        let again = generate(&generated.content, &TocOptions::default());
+        assert_eq!(again.content, generated.content);
+        assert!(!again.summary.changed());
+        assert_eq!(again.summary.unchanged, 4);
+    }
+
+    #[test]
+    fn generates_under_a_heading() {
+        let content = "# Post\n\n## Table of Contents\n\n- [Old](#old)\n  more\nIntro.\n\n## One\n";
+        let generated = generate(content, &TocOptions::default());
+        assert_eq!(
+            generated.content,
+            "# Post\n\n## Table of Contents\n\n- [One](#one-)\n\nIntro.\n\n## One\n"
+        );
+        assert_eq!((generated.summary.added, generated.summary.removed), (1, 1));
+
+        // Without a list or markers, there is nothing to replace.
+        let content = "# Post\n\n## One\n";
+        assert_eq!(generate(content, &TocOptions::default()).content, content);
+    }
+
+    #[test]
+    fn keeps_loose_lists_loose() {
+        let tight = generate(
+            &with_toc("- [One](#one-)\n- [Two](#two-)"),
+            &TocOptions::default(),
+        );
+        assert!(tight
+            .content
+            .contains("- [Two](#two-)\n- [Three](#three-)\n"));
+        let loose = generate(
+            &with_toc("- [One](#one-)\n\n- [Two](#two-)"),
+            &TocOptions::default(),
+        );
+        assert!(loose.content.contains(
+            "- [One](#one-)\n  - [One a](#one-a-)\n\n- [Two](#two-)\n\n- [Three](#three-)\n"
+        ));
+    }
+
+    #[test]
+    fn counts_entries() {
+        let content =
+            with_toc("- [One](#one-)\n  - [One A](#one-a-)\n- [Two](#two-)\n- [Four](#four-)");
+        let summary = generate(&content, &TocOptions::default()).summary;
+        assert_eq!(
+            summary,
+            HeadingSummary {
+                added: 1,
+                updated: 1,
+                unchanged: 2,
+                removed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_out_excluded_headings() {
+        let content = "<!-- toc -->\n<!-- tocstop -->\n\n\
+                       ## One\n\n## Two {: .no_toc}\n\n## Three\n\n## Four\n\n#### Deep\n";
+        let options = TocOptions {
+            exclude: vec!["three".to_string(), "four-".to_string()],
+            ..TocOptions::default()
+        };
+        let generated = generate(content, &options);
+        assert!(generated
+            .content
+            .starts_with("<!-- toc -->\n\n- [One](#one-)\n\n<!-- tocstop -->\n"));
+    }
+
+    #[test]
+    fn escapes_link_text() {
+        assert_eq!(
+            escape_link_text(r"a_b [c] *d* `e` \f"),
+            r"a\_b \[c\] \*d\* \`e\` \\f"
+        );
+    }
+
+    #[test]
+    fn markers_outside_code() {
+        let content =
+            "```\n<!-- toc -->\n```\n<!-- tocstop -->\n<!-- toc -->\n- x\n<!-- tocstop -->\n";
+        assert_eq!(find_markers(&Document::parse(content)), Some(5..6));
+        assert_eq!(find_markers(&Document::parse("<!-- toc -->\n")), None);
+        assert_eq!(
+            find_markers(&Document::parse("<!-- tocstop -->\n<!-- toc -->\n")),
+            None
+        );
+    }
+
+    #[test]
+    fn list_ends() {
+        let end = |content: &str, start| list_end(&Document::parse(content), start);
+        assert_eq!(end("## TOC\n\n- a\n  wrapped\n\n1. b\n\nText\n", 1), 6);
+        assert_eq!(end("## TOC\n- a\n## Next\n", 1), 2);
+        assert_eq!(end("## TOC\n\nText\n", 1), 1);
+    }
+}